@@ -1,9 +1,11 @@
 use std::f64;
 use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use wasm_bindgen::prelude::*;
 use crate::{wasm_ptr};
 use crate::node::Node;
-use crate::animation::{Bezier, easing};
+use crate::animation::{Bezier, Steps, LinearStops, Spring, easing, color};
+use crate::style::{style_unit, CalcItem, to_calc};
 
 pub const DEFAULT: u8 = 0;
 pub const LINEAR: u8 = 1;
@@ -12,6 +14,15 @@ pub const EASE_OUT: u8 = 3;
 pub const EASE: u8 = 4;
 pub const EASE_IN_OUT: u8 = 5;
 pub const EASE_CUSTOM: u8 = 6;
+pub const STEPS: u8 = 7;
+pub const LINEAR_STOPS: u8 = 8;
+pub const SPRING: u8 = 9;
+
+// steps()的跳跃位置，默认同css为jump-end
+pub const JUMP_END: u8 = 0;
+pub const JUMP_START: u8 = 1;
+pub const JUMP_NONE: u8 = 2;
+pub const JUMP_BOTH: u8 = 3;
 
 pub const NORMAL: u8 = 0;
 pub const REVERSE: u8 = 1;
@@ -23,16 +34,48 @@ pub const FORWARDS: u8 = 1;
 pub const BACKWARDS: u8 = 2;
 pub const BOTH: u8 = 3;
 
+// 同web animations的composite，多个动画作用于同一个key时的合成方式
+pub const REPLACE: u8 = 0;
+pub const ADD: u8 = 1;
+pub const ACCUMULATE: u8 = 2;
+
+// root.events中的事件类型
+pub const EVENT_FRAME: u8 = 0;
+pub const EVENT_BEGIN: u8 = 1;
+pub const EVENT_ITERATION: u8 = 2;
+pub const EVENT_END: u8 = 3;
+pub const EVENT_FINISH: u8 = 4;
+pub const EVENT_CANCEL: u8 = 5;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
 pub const IDLE: u8 = 0;
 pub const RUNNING: u8 = 1;
 pub const PAUSED: u8 = 2;
 pub const FINISH: u8 = 3;
 
+// 颜色插值空间，DEFAULT的帧使用动画的设置
+pub const SRGB: u8 = 1;
+pub const LINEAR_RGB: u8 = 2;
+pub const OKLAB: u8 = 3;
+pub const OKLCH: u8 = 4;
+
+// oklch的hue插值方向
+pub const HUE_SHORTER: u8 = 0;
+pub const HUE_LONGER: u8 = 1;
+pub const HUE_INCREASING: u8 = 2;
+pub const HUE_DECREASING: u8 = 3;
+
 struct FrameItem {
   k: usize,
   v: f64,
   u: usize,
   d: f64,
+  calc: Vec<CalcItem>, // 单位为calc时，js需将前后两帧对齐成相同的各项
+  calc_d: Vec<f64>,
+  c: [f64; 4], // 单位为rgba时的颜色值和差值
+  cd: [f64; 4],
+  v0: f64, // 继承的初始速度，单位每ms，仅首轮生效
 }
 
 impl FrameItem {
@@ -42,6 +85,11 @@ impl FrameItem {
       v,
       u,
       d,
+      calc: Vec::new(),
+      calc_d: Vec::new(),
+      c: [0.0; 4],
+      cd: [0.0; 4],
+      v0: 0.0,
     }
   }
 }
@@ -51,6 +99,8 @@ struct Frame {
   time: f64,
   easing: u8,
   bezier: easing::BezierEnum,
+  color_space: u8,
+  hue: u8,
 }
 
 impl Frame {
@@ -60,6 +110,8 @@ impl Frame {
       time,
       easing,
       bezier,
+      color_space: DEFAULT,
+      hue: HUE_SHORTER,
     }
   }
 }
@@ -68,6 +120,8 @@ struct Style {
   k: usize,
   v: f64,
   u: usize,
+  calc: Vec<CalcItem>,
+  c: [f64; 4],
 }
 
 impl Style {
@@ -76,18 +130,24 @@ impl Style {
       k,
       v,
       u,
+      calc: Vec::new(),
+      c: [0.0; 4],
     }
   }
 }
 
+#[derive(Clone)]
 pub(crate) struct Transition {
   pub k: usize,
   pub v: f64,
   pub u: usize,
+  pub calc: Vec<CalcItem>,
+  pub c: [f64; 4],
 }
 
 #[wasm_bindgen]
 pub struct Animation {
+  pub id: usize, // 自增唯一，事件中用来区分动画
   node: *mut Node,
   frames: Vec<Frame>,
   frames_r: Vec<Frame>,
@@ -103,6 +163,13 @@ pub struct Animation {
   pub area_duration: f64,
   pub easing: u8,
   bezier: [f64; 4],
+  steps: usize,
+  step_position: u8,
+  linear_stops: LinearStops,
+  spring: Option<Spring>, // 仅设置spring时创建，需模拟计算静止时间
+  pub spring_mode: bool, // 无duration的spring模式，duration由静止时间决定
+  pub color_space: u8,
+  pub hue: u8,
   pub current_time: f64,
   pub play_count: usize,
   pub play_state: u8,
@@ -113,6 +180,7 @@ pub struct Animation {
   is_delay: bool,
   is_end_delay: bool,
   begin: bool,
+  iteration: bool,
   end: bool,
   pub finished: bool,
   last_index: usize,
@@ -121,6 +189,10 @@ pub struct Animation {
   pub percent: f64,
   transition: Vec<Transition>,
   origin: Vec<Style>,
+  pub(crate) implicit: bool, // 节点css transition自动生成的动画，js无感知，不产生事件
+  pub(crate) in_group: bool, // 由AnimationGroup驱动，node不再执行
+  pub composite: u8,
+  applied: Vec<Transition>, // 非replace时本动画当前作用的全部值，由node叠加在基础样式上
 }
 
 #[wasm_bindgen]
@@ -129,7 +201,16 @@ impl Animation {
              delay: f64, end_delay: f64, fill: u8, playback_rate: f64,
              iterations: usize, area_start: f64, area_duration: f64, easing: u8) -> Animation {
     let node = wasm_ptr::transform_mut(node);
+    Animation::from_node(node, direction, duration, fps, delay, end_delay, fill, playback_rate,
+      iterations, area_start, area_duration, easing)
+  }
+
+  // node为rust侧指针，无需转换
+  pub(crate) fn from_node(node: *mut Node, direction: u8, duration: f64, fps: usize,
+                          delay: f64, end_delay: f64, fill: u8, playback_rate: f64,
+                          iterations: usize, area_start: f64, area_duration: f64, easing: u8) -> Animation {
     Animation {
+      id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
       node,
       frames: Vec::new(),
       frames_r: Vec::new(),
@@ -145,6 +226,13 @@ impl Animation {
       area_duration,
       easing,
       bezier: [0.0, 0.0, 1.0, 1.0],
+      steps: 1,
+      step_position: JUMP_END,
+      linear_stops: LinearStops::new(Vec::new(), Vec::new()),
+      spring: None,
+      spring_mode: false,
+      color_space: SRGB,
+      hue: HUE_SHORTER,
       current_time: 0.0,
       play_count: 0,
       play_state: 0,
@@ -155,6 +243,7 @@ impl Animation {
       is_delay: false,
       is_end_delay: false,
       begin: true,
+      iteration: false,
       end: false,
       finished: false,
       last_index: 0,
@@ -163,6 +252,10 @@ impl Animation {
       percent: -1.0,
       transition: Vec::new(),
       origin: Vec::new(),
+      implicit: false,
+      in_group: false,
+      composite: REPLACE,
+      applied: Vec::new(),
     }
   }
 
@@ -174,6 +267,43 @@ impl Animation {
     self.easing = EASE_CUSTOM;
   }
 
+  pub fn set_steps(&mut self, n: usize, position: u8) -> () {
+    self.steps = n;
+    self.step_position = position;
+    self.easing = STEPS;
+  }
+
+  // css的linear()，input中NaN为未指定位置
+  pub fn set_linear(&mut self, input: Vec<f64>, output: Vec<f64>) -> () {
+    self.linear_stops = LinearStops::new(input, output);
+    self.easing = LINEAR_STOPS;
+  }
+
+  // spring作为easing，返回静止所需时间ms供js参考设置duration
+  pub fn set_spring(&mut self, mass: f64, stiffness: f64, damping: f64, velocity: f64) -> f64 {
+    let spring = Spring::new(mass, stiffness, damping, velocity, easing::REST_DISPLACEMENT, easing::REST_VELOCITY);
+    let duration = spring.duration;
+    self.spring = Some(spring);
+    self.easing = SPRING;
+    duration
+  }
+
+  // 无duration的spring模式，运行到位移和速度均低于阈值为止，仅支持起止2帧，返回计算出的duration
+  pub fn set_spring_mode(&mut self, mass: f64, stiffness: f64, damping: f64, velocity: f64,
+                         rest_displacement: f64, rest_velocity: f64) -> f64 {
+    let spring = Spring::new(mass, stiffness, damping, velocity, rest_displacement, rest_velocity);
+    self.easing = SPRING;
+    self.spring_mode = true;
+    self.duration = spring.duration;
+    self.area_duration = 0.0;
+    for frame in self.frames.iter_mut().chain(self.frames_r.iter_mut()) {
+      frame.easing = SPRING;
+      frame.bezier = easing::BezierEnum::Spring(spring.clone());
+    }
+    self.spring = Some(spring);
+    self.duration
+  }
+
   pub fn add_frame(&mut self, is_reverse: bool, time: f64, easing: u8, x1: f64, y1: f64, x2: f64, y2: f64) -> () {
     let bezier: easing::BezierEnum = if easing == EASE_IN {
       easing::BezierEnum::EaseIn
@@ -198,6 +328,12 @@ impl Animation {
         easing::BezierEnum::EaseInOut
       } else if self.easing == EASE_CUSTOM {
         easing::BezierEnum::Custom(Bezier::new(self.bezier[0],self.bezier[1], self.bezier[2], self.bezier[3]))
+      } else if self.easing == STEPS {
+        easing::BezierEnum::Steps(Steps::new(self.steps, self.step_position))
+      } else if self.easing == LINEAR_STOPS {
+        easing::BezierEnum::LinearStops(self.linear_stops.clone())
+      } else if let (SPRING, Some(spring)) = (self.easing, &self.spring) {
+        easing::BezierEnum::Spring(spring.clone())
       } else {
         easing::BezierEnum::Linear
       }
@@ -209,6 +345,95 @@ impl Animation {
     }
   }
 
+  // 帧的easing为steps(n, position)
+  pub fn add_frame_steps(&mut self, is_reverse: bool, time: f64, n: usize, position: u8) -> () {
+    let bezier = easing::BezierEnum::Steps(Steps::new(n, position));
+    if is_reverse {
+      self.frames_r.push(Frame::new(time, STEPS, bezier));
+    } else {
+      self.frames.push(Frame::new(time, STEPS, bezier));
+    }
+  }
+
+  // 帧的easing为linear()，input和output为各点的输入输出
+  pub fn add_frame_linear(&mut self, is_reverse: bool, time: f64, input: Vec<f64>, output: Vec<f64>) -> () {
+    let bezier = easing::BezierEnum::LinearStops(LinearStops::new(input, output));
+    if is_reverse {
+      self.frames_r.push(Frame::new(time, LINEAR_STOPS, bezier));
+    } else {
+      self.frames.push(Frame::new(time, LINEAR_STOPS, bezier));
+    }
+  }
+
+  pub fn add_frame_spring(&mut self, is_reverse: bool, time: f64,
+                          mass: f64, stiffness: f64, damping: f64, velocity: f64) -> () {
+    let bezier = easing::BezierEnum::Spring(Spring::new(mass, stiffness, damping, velocity,
+      easing::REST_DISPLACEMENT, easing::REST_VELOCITY));
+    if is_reverse {
+      self.frames_r.push(Frame::new(time, SPRING, bezier));
+    } else {
+      self.frames.push(Frame::new(time, SPRING, bezier));
+    }
+  }
+
+  // 当前时刻k的即时速度，单位每ms，用于打断动画时后续动画继承，仅支持普通数值
+  pub fn get_velocity(&self, k: usize) -> f64 {
+    // 结束时play_count会超过最后一轮，play_state由js设置可能仍为RUNNING
+    if self.play_state != RUNNING || self.index < 0 || self.is_delay || self.is_end_delay
+      || self.play_count >= self.iterations {
+      return 0.0
+    }
+    let current_frames = if self.is_reverse { &self.frames_r } else { &self.frames };
+    let index = self.index as usize;
+    if index + 1 >= current_frames.len() {
+      return 0.0
+    }
+    let frame = &current_frames[index];
+    let dur = frame_duration(current_frames, index, self.duration);
+    if dur <= 0.0 {
+      return 0.0
+    }
+    let p = self.percent;
+    for item in frame.list.iter() {
+      if item.k == k && item.u != style_unit::CALC && item.u != style_unit::RGBA {
+        let mut v = item.d * frame.bezier.derivative(p) / dur;
+        if self.play_count == 0 && index == 0 {
+          v += item.v0 * (1.0 - p) * (1.0 - 3.0 * p);
+        }
+        return v * self.playback_rate
+      }
+    }
+    0.0
+  }
+
+  // 设置首帧上k的初始速度，单位每ms，通常来自被打断动画的get_velocity
+  // 以v0*帧时长*p*(1-p)^2叠加在easing结果上，首尾数值不变，开始时速度为v0，spring同样适用
+  pub fn set_velocity(&mut self, k: usize, v: f64) -> () {
+    let v = if self.playback_rate != 0.0 { v / self.playback_rate } else { 0.0 };
+    for fs in [&mut self.frames, &mut self.frames_r] {
+      if let Some(frame) = fs.first_mut() {
+        for item in frame.list.iter_mut() {
+          if item.k == k {
+            item.v0 = v;
+          }
+        }
+      }
+    }
+  }
+
+  // 设置最后添加的帧的颜色插值空间，DEFAULT则使用动画的color_space和hue
+  pub fn set_frame_color_space(&mut self, is_reverse: bool, color_space: u8, hue: u8) -> () {
+    let fs = if is_reverse { &mut self.frames_r } else { &mut self.frames };
+    let wf = fs.last_mut();
+    match wf {
+      Some(x) => {
+        x.color_space = color_space;
+        x.hue = hue;
+      },
+      None => panic!(),
+    }
+  }
+
   pub fn add_item(&mut self, is_reverse: bool, k: usize, v: f64, u: usize, d: f64) -> () {
     let fs = if is_reverse { &mut self.frames_r } else { &mut self.frames };
     let wf = fs.last_mut();
@@ -220,16 +445,66 @@ impl Animation {
     }
   }
 
+  pub fn add_item_calc(&mut self, is_reverse: bool, k: usize, v: Vec<f64>, u: Vec<usize>, d: Vec<f64>) -> () {
+    let fs = if is_reverse { &mut self.frames_r } else { &mut self.frames };
+    let wf = fs.last_mut();
+    match wf {
+      Some(x) => {
+        let mut item = FrameItem::new(k, 0.0, style_unit::CALC, 0.0);
+        item.calc = to_calc(&v, &u);
+        item.calc_d = d;
+        x.list.push(item);
+      },
+      None => panic!(),
+    }
+  }
+
+  // 颜色用rgba4个分量，d为和下一帧的差值
+  pub fn add_item_color(&mut self, is_reverse: bool, k: usize, r: f64, g: f64, b: f64, a: f64,
+                        dr: f64, dg: f64, db: f64, da: f64) -> () {
+    let fs = if is_reverse { &mut self.frames_r } else { &mut self.frames };
+    let wf = fs.last_mut();
+    match wf {
+      Some(x) => {
+        let mut item = FrameItem::new(k, 0.0, style_unit::RGBA, 0.0);
+        item.c = [r, g, b, a];
+        item.cd = [dr, dg, db, da];
+        x.list.push(item);
+      },
+      None => panic!(),
+    }
+  }
+
   pub fn add_origin(&mut self, k: usize, v: f64, u: usize) -> () {
     self.origin.push(Style::new(k, v, u));
   }
 
+  pub fn add_origin_calc(&mut self, k: usize, v: Vec<f64>, u: Vec<usize>) -> () {
+    let mut style = Style::new(k, 0.0, style_unit::CALC);
+    style.calc = to_calc(&v, &u);
+    self.origin.push(style);
+  }
+
+  pub fn add_origin_color(&mut self, k: usize, r: f64, g: f64, b: f64, a: f64) -> () {
+    let mut style = Style::new(k, 0.0, style_unit::RGBA);
+    style.c = [r, g, b, a];
+    self.origin.push(style);
+  }
+
+  // playback_rate为负时从结尾开始倒放
   pub fn play(&mut self) {
-    self.current_time = 0_f64;
-    self.play_count = 0;
+    if self.playback_rate < 0.0 {
+      self.current_time = self.active_duration();
+      self.play_count = if self.iterations > 0 { self.iterations - 1 } else { 0 };
+    } else {
+      self.current_time = 0_f64;
+      self.play_count = 0;
+    }
+    self.init_current_frames(self.play_count);
     self.play_state = RUNNING;
     self.first_play = true;
     self.begin = true;
+    self.iteration = false;
     self.end = false;
     self.is_delay = false;
     self.is_end_delay = false;
@@ -255,7 +530,8 @@ impl Animation {
     let is_last_count = self.play_count >= self.iterations - 1;
     let length = current_frames.len();
     let play_count = self.play_count;
-    let current_time = self.current_time - dur * (play_count as f64);
+    // 减去delay和之前的轮次，goto时可能处于delay中，按开头计算
+    let current_time = f64::max(0.0, self.current_time - (self.delay - self.area_start) - dur * (play_count as f64));
     // 只有2帧可优化，否则2分查找当前帧
     let index = if length == 2 {
       if current_time < dur { 0 } else { 1 }
@@ -279,14 +555,26 @@ impl Animation {
     }
     self.transition.clear();
     // 最后结束特殊处理
-    if is_last_frame {
+    if is_last_frame && self.playback_rate < 0.0 {
+      // 倒放时从endDelay开始，同fill保持末帧或origin，结束在开头处理
+      if !self.is_end_delay {
+        self.is_end_delay = true;
+        let node = unsafe { & *self.node };
+        if self.fill == FORWARDS || self.fill == BOTH {
+          self.transition = cal_last_style(node, current_frame, self.composite != REPLACE);
+        } else {
+          self.applied.clear();
+          self.transition = self.cal_origin_style();
+        }
+      }
+    } else if is_last_frame {
       if self.fill == FORWARDS || self.fill == BOTH {
         // 第一次进入endDelay触发后续不再，并且设置__end标识在after触发END事件
         if !self.is_end_delay {
           self.is_end_delay = true;
           self.end = true;
           let node = unsafe { & *self.node };
-          self.transition = cal_last_style(node, current_frame);
+          self.transition = cal_last_style(node, current_frame, self.composite != REPLACE);
         }
         // 有可能刚进endDelay（只有1ms很短）就超过直接finish了，所以只用时间对比
         if current_time >= dur + self.end_delay {
@@ -294,64 +582,70 @@ impl Animation {
           self.finished = true;
         }
       } else {
-        // 恢复originStyle
+        // 恢复originStyle，非replace的移除自身作用即可
         self.end = true;
         self.play_count += 1;
         self.finished = true;
-        let node = unsafe { & *self.node };
-        for item in self.origin.iter() {
-          if !node.equal_style(item.k, item.v, item.u) {
-            self.transition.push(Transition {
-              k: item.k,
-              v: item.v,
-              u: item.u,
-            });
-          }
-        }
+        self.applied.clear();
+        self.transition = self.cal_origin_style();
       }
     } else {
+      self.is_end_delay = false;
       // 对比前后两帧是否为同一关键帧，不是则清除之前关键帧上的percent标识为-1，这样可以识别跳帧和本轮第一次进入此帧
       // 这里和js不同，由于不需要回调，前置写在这里判断是否需要计算transition
       if self.index == -1 || (index as isize) != (self.index as isize) || percent != self.percent {
         self.index = index as isize;
         self.percent = percent;
-        self.transition = cal_intermediate_style(current_frame, percent);
+        // 初始速度仅首轮首帧生效，帧时长为0则不叠加
+        let v0_dur = if self.play_count == 0 && index == 0 {
+          frame_duration(current_frames, index, self.duration)
+        } else {
+          0.0
+        };
+        self.transition = cal_intermediate_style(current_frame, percent, self.color_space, self.hue, v0_dur,
+          self.composite != REPLACE);
       }
       // 和js不同无需处理，等待root刷新计算调用
     }
+    self.update_applied();
     self.transition.len() > 0
   }
 
   pub fn before(&mut self, mut diff: f64) -> bool {
-    let dur = if self.area_duration > 0.0 {
-      f64::min(self.area_duration, self.duration)
-    } else {
-      self.duration
-    };
     // 播放时间累加，并且考虑播放速度加成
     if self.playback_rate != 1.0 {
       diff *= self.playback_rate;
     }
     // 用本帧和上帧时间差，计算累加运行时间currentTime，以便定位当前应该处于哪个时刻
     self.current_time += diff;
+    self.update(diff)
+  }
+
+  // current_time已更新，diff为本次增加的时间，group驱动时直接设置current_time后调用
+  pub(crate) fn update(&mut self, mut diff: f64) -> bool {
+    let dur = self.get_dur();
     let mut current_time = self.current_time;
     // 增加的fps功能，当<60时计算跳帧，每帧运行依旧累加时间，达到fps时重置，第一帧强制不跳
     if !self.first_play && self.fps > 0 && self.fps != 60 && self.fps != 120 {
-      self.fps_time += diff;
+      self.fps_time += diff.abs();
       diff = self.fps_time;
       if diff < 1000.0 / (self.fps as f64) {
         self.in_fps = true;
         return false
       }
     }
-    if current_time < self.delay - self.area_start {
+    let delay = self.delay - self.area_start;
+    if current_time < delay {
+      if self.playback_rate < 0.0 {
+        return self.cal_start()
+      }
       self.begin = false; // 默认是true，delay置false防触发
       // 即便不刷新，依旧执行帧回调，同时标明让后续第一帧响应begin
       self.is_delay = true;
       return false
     }
     // 减去delay，计算在哪一帧
-    current_time -= self.delay - self.area_start;
+    current_time -= delay;
     if self.is_delay {
       self.is_delay = false;
       self.begin = true;
@@ -361,52 +655,136 @@ impl Animation {
     if play_count > self.iterations - 1 {
       play_count = self.iterations - 1;
     }
-    current_time -= dur * (play_count as f64);
-    // 如果发生轮换，需重新确定正反向
-    if self.play_count < play_count {
-      self.begin = true;
+    // 如果发生轮换，需重新确定正反向，倒放时轮次减少
+    if self.play_count != play_count {
+      self.iteration = true;
       self.play_count = play_count;
       self.init_current_frames(play_count);
     }
     self.cal_current(dur)
   }
 
-  // 用01248来枚举当前状态，返回是否finish
+  // 将本帧发生的事件按frame、begin、iteration、end、finish的顺序加入root.events，返回是否finish
   pub fn after(&mut self) -> bool {
-    let node = unsafe { & *self.node };
-    let root = unsafe { &mut *node.root };
     if self.in_fps {
       self.in_fps = false;
-      root.add_am_state(0);
-      return false
+    } else {
+      self.add_event(EVENT_FRAME);
     }
-    let mut n = 1;
     let mut res = false;
     if self.begin {
       self.begin = false;
-      n += 2;
+      self.add_event(EVENT_BEGIN);
+    }
+    if self.iteration {
+      self.iteration = false;
+      self.add_event(EVENT_ITERATION);
     }
     if self.end {
       self.end = false;
-      n += 4;
+      self.add_event(EVENT_END);
     }
     if self.finished {
       self.begin = false;
+      self.iteration = false;
       self.end = false;
       self.is_delay = false;
       self.is_end_delay = false;
       self.finished = false;
-      n += 8;
+      self.add_event(EVENT_FINISH);
       res = true;
     }
-    root.add_am_state(n);
     res
   }
 
+  // transition无需事件，未添加到root的也无法记录
+  fn add_event(&self, kind: u8) -> () {
+    let node = unsafe { & *self.node };
+    if self.implicit || node.root.is_null() {
+      return
+    }
+    let root = unsafe { &mut *node.root };
+    // 结束时play_count已超过最后一轮，事件中为最后一轮的下标
+    let iteration = cmp::min(self.play_count, self.iterations.saturating_sub(1));
+    root.add_event(kind, self.id, iteration, self.current_time);
+  }
+
   pub fn goto_stop(&mut self, v: f64, dur: f64) -> bool {
     self.play_state = PAUSED;
+    self.seek(v, dur)
+  }
+
+  // 同goto_stop，之后继续播放
+  pub fn goto_and_play(&mut self, v: f64, dur: f64) -> bool {
+    self.play_state = RUNNING;
+    self.seek(v, dur)
+  }
+
+  // 直接跳到结束，倒放时为开头，根据fill保留首末帧或恢复origin，触发end和finish事件
+  pub fn finish(&mut self) -> bool {
+    let dur = self.get_dur();
+    if self.iterations == 0 || dur <= 0.0 {
+      return false
+    }
+    self.reset_state();
+    let res = if self.playback_rate < 0.0 {
+      self.current_time = 0.0;
+      self.cal_start()
+    } else {
+      self.current_time = self.active_duration();
+      self.play_count = self.iterations - 1;
+      self.init_current_frames(self.play_count);
+      self.cal_current(dur)
+    };
+    if res {
+      let node = unsafe { &mut *self.node };
+      node.cal_trans(self);
+    }
+    self.add_event(EVENT_END);
+    self.add_event(EVENT_FINISH);
+    self.reset_state();
+    self.play_state = FINISH;
+    res
+  }
+
+  // 取消动画，无视fill恢复origin，触发cancel事件
+  pub fn cancel(&mut self) -> bool {
+    self.reset_state();
+    self.applied.clear();
+    self.transition = self.cal_origin_style();
+    let node = unsafe { &mut *self.node };
+    let res = !self.transition.is_empty() || self.composite != REPLACE;
+    if res {
+      node.cal_trans(self);
+    }
+    self.add_event(EVENT_CANCEL);
+    self.current_time = 0.0;
+    self.play_count = 0;
+    self.play_state = IDLE;
+    res
+  }
+
+  // 原地反向播放，同web animations即playback_rate取反，当前画面不变，不触发begin
+  // 未播放或已取消的从反向的开头开始播放
+  pub fn reverse(&mut self) -> () {
+    if self.play_state == IDLE {
+      self.playback_rate = -self.playback_rate;
+      self.play();
+      return
+    }
+    let total = self.active_duration();
+    self.current_time = f64::min(total, f64::max(0.0, self.current_time));
+    self.playback_rate = -self.playback_rate;
+    self.reset_state();
+    self.begin = false;
+    // 同web animations，暂停和结束的都恢复播放
+    self.play_state = RUNNING;
+  }
+
+  fn seek(&mut self, v: f64, dur: f64) -> bool {
+    self.reset_state();
     self.current_time = v;
-    self.play_count = (v / dur).floor() as usize;
+    self.play_count = ((v - (self.delay - self.area_start)) / dur).floor() as usize;
     if self.play_count > self.iterations - 1 {
       self.play_count = self.iterations - 1;
     }
@@ -419,6 +797,123 @@ impl Animation {
     res
   }
 
+  fn update_applied(&mut self) -> () {
+    if self.composite != REPLACE {
+      for item in self.transition.iter() {
+        match self.applied.iter_mut().find(|x| x.k == item.k) {
+          Some(x) => *x = item.clone(),
+          None => self.applied.push(item.clone()),
+        }
+      }
+    }
+  }
+
+  // 倒放进入delay，同fill保持首帧或恢复origin，到达0时结束
+  pub(crate) fn cal_start(&mut self) -> bool {
+    self.transition.clear();
+    if !self.is_delay {
+      self.is_delay = true;
+      self.end = true;
+      self.play_count = 0;
+      self.init_current_frames(0);
+      if self.fill == BACKWARDS || self.fill == BOTH {
+        let current_frames = if self.is_reverse { &self.frames_r } else { &self.frames };
+        if let Some(frame) = current_frames.first() {
+          let node = unsafe { & *self.node };
+          self.transition = cal_last_style(node, frame, self.composite != REPLACE);
+        }
+      } else {
+        self.applied.clear();
+        self.transition = self.cal_origin_style();
+      }
+    }
+    if self.current_time <= 0.0 {
+      self.finished = true;
+    }
+    self.update_applied();
+    !self.transition.is_empty()
+  }
+
+  // 恢复originStyle，非replace的移除自身作用即可无需恢复
+  fn cal_origin_style(&self) -> Vec<Transition> {
+    let mut ts: Vec<Transition> = Vec::new();
+    if self.composite != REPLACE {
+      return ts
+    }
+    let node = unsafe { & *self.node };
+    for item in self.origin.iter() {
+      if !equal_style(node, item.k, item.v, item.u, &item.calc, &item.c) {
+        ts.push(Transition {
+          k: item.k,
+          v: item.v,
+          u: item.u,
+          calc: item.calc.clone(),
+          c: item.c,
+        });
+      }
+    }
+    ts
+  }
+
+  fn get_dur(&self) -> f64 {
+    if self.area_duration > 0.0 {
+      f64::min(self.area_duration, self.duration)
+    } else {
+      self.duration
+    }
+  }
+
+  // 清除播放中的状态标识，index置-1强制重新计算当前帧
+  pub(crate) fn reset_state(&mut self) -> () {
+    self.iteration = false;
+    self.end = false;
+    self.finished = false;
+    self.is_delay = false;
+    self.is_end_delay = false;
+    self.in_fps = false;
+    self.index = -1;
+  }
+
+  // group驱动时本帧不在自身时间范围内，丢弃期间产生的事件标识
+  pub(crate) fn drop_events(&mut self) -> () {
+    self.begin = false;
+    self.iteration = false;
+    self.end = false;
+    self.finished = false;
+    self.in_fps = false;
+  }
+
+  pub(crate) fn has_key(&self, k: usize) -> bool {
+    match self.frames.first() {
+      Some(x) => x.list.iter().any(|item| item.k == k),
+      None => false,
+    }
+  }
+
+  // 末帧上k的值是否和参数相同，transition用来判断目标值未变
+  pub(crate) fn is_target(&self, k: usize, v: f64, u: usize, c: &[f64; 4]) -> bool {
+    match self.frames.last() {
+      Some(x) => x.list.iter().any(|item| {
+        item.k == k && item.u == u && if u == style_unit::RGBA { item.c == *c } else { item.v == v }
+      }),
+      None => false,
+    }
+  }
+
+  pub(crate) fn get_node(&self) -> *mut Node {
+    self.node
+  }
+
+  // 单次播放的总时长，含delay、所有轮次和endDelay
+  pub(crate) fn active_duration(&self) -> f64 {
+    let dur = self.get_dur();
+    self.delay - self.area_start + dur * (self.iterations as f64) + self.end_delay
+  }
+
+  pub(crate) fn get_applied(&self) -> &Vec<Transition> {
+    &self.applied
+  }
+
   pub(crate) fn get_transition(&mut self) -> &Vec<Transition> {
     &self.transition
   }
@@ -427,6 +922,7 @@ impl Animation {
     self.frames.clear();
     self.frames_r.clear();
     self.transition.clear();
+    self.applied.clear();
   }
 }
 
@@ -449,51 +945,375 @@ fn binary_search(mut i: usize, mut j: usize, time: f64, frames: &Vec<Frame>) ->
   i
 }
 
-fn cal_intermediate_style(current_frame: &Frame, mut percent: f64) -> Vec<Transition> {
-  // bezier计算percent
-  percent = match &current_frame.bezier {
-    easing::BezierEnum::Ease => {
-      easing::EASE.timing_function(percent)
-    }
-    easing::BezierEnum::EaseIn => {
-      easing::EASE_IN.timing_function(percent)
-    }
-    easing::BezierEnum::EaseOut => {
-      easing::EASE_OUT.timing_function(percent)
-    }
-    easing::BezierEnum::EaseInOut => {
-      easing::EASE_IN_OUT.timing_function(percent)
-    }
-    easing::BezierEnum::Custom(b) => {
-      b.timing_function(percent)
-    }
-    _ => {
-      percent
-    }
+fn frame_duration(frames: &[Frame], index: usize, duration: f64) -> f64 {
+  // 和cal_current一致，只有2帧时按照原本duration
+  if frames.len() == 2 {
+    return duration
+  }
+  frames[index + 1].time - frames[index].time
+}
+
+// all为true时不变的值也包含，非replace的动画需要全部的值来合成
+fn cal_intermediate_style(current_frame: &Frame, percent: f64, color_space: u8, hue: u8, v0_dur: f64,
+                          all: bool) -> Vec<Transition> {
+  // 继承速度的叠加量，和easing无关，用原始percent
+  let v0_p = v0_dur * percent * (1.0 - percent) * (1.0 - percent);
+  // easing计算percent
+  let percent = current_frame.bezier.timing_function(percent);
+  let (color_space, hue) = if current_frame.color_space == DEFAULT {
+    (color_space, hue)
+  } else {
+    (current_frame.color_space, current_frame.hue)
   };
   let mut ts: Vec<Transition> = Vec::new();
   for item in current_frame.list.iter() {
-    if item.d != 0.0 {
+    if item.u == style_unit::CALC {
+      if all || item.calc_d.iter().any(|&d| d != 0.0) {
+        ts.push(Transition {
+          k: item.k,
+          v: item.v,
+          u: item.u,
+          calc: item.calc.iter().zip(item.calc_d.iter())
+            .map(|(c, &d)| CalcItem::new(c.v + d * percent, c.u)).collect(),
+          c: [0.0; 4],
+        });
+      }
+    } else if item.u == style_unit::RGBA {
+      if all || item.cd.iter().any(|&d| d != 0.0) {
+        ts.push(Transition {
+          k: item.k,
+          v: item.v,
+          u: item.u,
+          calc: Vec::new(),
+          c: color::interpolate(&item.c, &item.cd, percent, color_space, hue),
+        });
+      }
+    } else if all || item.d != 0.0 || item.v0 != 0.0 {
       ts.push(Transition {
         k: item.k,
-        v: item.v + item.d * percent,
+        v: item.v + item.d * percent + item.v0 * v0_p,
         u: item.u,
+        calc: Vec::new(),
+        c: [0.0; 4],
       });
     }
   }
   ts
 }
 
-fn cal_last_style(node: &Node, current_frame: &Frame) -> Vec<Transition> {
+fn cal_last_style(node: &Node, current_frame: &Frame, all: bool) -> Vec<Transition> {
   let mut ts: Vec<Transition> = Vec::new();
   for item in current_frame.list.iter() {
-    if !node.equal_style(item.k, item.v, item.u) {
+    if all || !equal_style(node, item.k, item.v, item.u, &item.calc, &item.c) {
       ts.push(Transition {
         k: item.k,
         v: item.v,
         u: item.u,
+        calc: item.calc.clone(),
+        c: item.c,
       });
     }
   }
   ts
 }
+
+fn equal_style(node: &Node, k: usize, v: f64, u: usize, calc: &[CalcItem], c: &[f64; 4]) -> bool {
+  if u == style_unit::CALC {
+    return node.equal_calc(k, calc)
+  }
+  if u == style_unit::RGBA {
+    return node.equal_color(k, c)
+  }
+  node.equal_style(k, v, u)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::node::Root;
+  use crate::test_util::{get, add_node, add_ani, translate_x, events, kinds, tick};
+  use crate::style::style_key::{TRANSLATE_X, COLOR};
+
+  // translateX在100ms内从0到100，fill为forwards
+  fn create(root: &mut Root, playback_rate: f64) -> (*mut Node, &'static mut Animation) {
+    let node = add_node(root, 0, 0.0, 0.0, 10.0, 10.0);
+    let ani = add_ani(node, 100.0, FORWARDS, playback_rate, 1);
+    (node, get(ani))
+  }
+
+  #[test]
+  fn finish() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 30.0);
+    root.clear_events();
+    assert!(ani.finish());
+    assert_eq!(translate_x(node), 100.0);
+    assert_eq!(kinds(&root), vec![EVENT_END, EVENT_FINISH]);
+    assert_eq!(ani.play_state, FINISH);
+  }
+
+  #[test]
+  fn cancel() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    root.clear_events();
+    assert!(ani.cancel());
+    // 无视fill恢复origin
+    assert_eq!(translate_x(node), 5.0);
+    assert_eq!(kinds(&root), vec![EVENT_CANCEL]);
+    assert_eq!(ani.play_state, IDLE);
+    assert_eq!(ani.current_time, 0.0);
+  }
+
+  #[test]
+  fn reverse() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    ani.reverse();
+    assert_eq!(ani.playback_rate, -1.0);
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 20.0);
+    assert!(!kinds(&root).contains(&EVENT_BEGIN));
+    tick(&mut root, 30.0);
+    assert!(kinds(&root).contains(&EVENT_FINISH));
+    // 倒放到开头处于before阶段，fill为forwards时不保留首帧
+    assert_eq!(translate_x(node), 5.0);
+  }
+
+  #[test]
+  fn reverse_idle() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    // 未播放过的直接从结尾倒放
+    ani.reverse();
+    assert_eq!(ani.play_state, RUNNING);
+    assert_eq!(ani.current_time, 100.0);
+    tick(&mut root, 0.0);
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 90.0);
+  }
+
+  #[test]
+  fn goto_and_play() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    ani.goto_stop(50.0, 100.0);
+    assert_eq!(ani.play_state, PAUSED);
+    assert_eq!(translate_x(node), 50.0);
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 50.0);
+    ani.goto_and_play(20.0, 100.0);
+    assert_eq!(ani.play_state, RUNNING);
+    assert_eq!(translate_x(node), 20.0);
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 30.0);
+  }
+
+  #[test]
+  fn negative_rate() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, -1.0);
+    ani.play();
+    assert_eq!(ani.current_time, 100.0);
+    tick(&mut root, 0.0);
+    assert_eq!(translate_x(node), 100.0);
+    tick(&mut root, 40.0);
+    assert_eq!(translate_x(node), 60.0);
+    tick(&mut root, 70.0);
+    assert!(kinds(&root).contains(&EVENT_FINISH));
+    assert_eq!(translate_x(node), 5.0);
+  }
+
+  #[test]
+  fn change_rate() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 40.0);
+    assert_eq!(translate_x(node), 40.0);
+    // 播放中修改速度从当前时间继续
+    ani.playback_rate = 2.0;
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 60.0);
+    ani.playback_rate = -1.0;
+    tick(&mut root, 20.0);
+    assert_eq!(translate_x(node), 40.0);
+    ani.playback_rate = 0.0;
+    tick(&mut root, 20.0);
+    assert_eq!(translate_x(node), 40.0);
+    assert_eq!(ani.play_state, RUNNING);
+  }
+
+  #[test]
+  fn spring_mode() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    assert!(ani.spring.is_none());
+    let duration = ani.set_spring_mode(1.0, 100.0, 20.0, 0.0, 0.001, 0.01);
+    assert!(duration > 0.0);
+    assert!(ani.spring_mode);
+    assert_eq!(ani.duration, duration);
+    assert!(ani.frames.iter().all(|f| f.easing == SPRING));
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, duration * 0.5);
+    let x = translate_x(node);
+    assert!(x > 50.0 && x < 100.0);
+    tick(&mut root, duration * 0.5);
+    assert_eq!(translate_x(node), 100.0);
+    assert!(kinds(&root).contains(&EVENT_FINISH));
+  }
+
+  fn color(node: *mut Node) -> Vec<f64> {
+    unsafe { std::slice::from_raw_parts((*node).color_ptr(), 4) }.to_vec()
+  }
+
+  // color从不透明红色到透明蓝色
+  fn create_color(root: &mut Root) -> (*mut Node, &'static mut Animation) {
+    let node = add_node(root, 0, 0.0, 0.0, 10.0, 10.0);
+    let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 1, 0.0, 0.0, LINEAR);
+    ani.add_frame(false, 0.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_color(false, COLOR, 255.0, 0.0, 0.0, 1.0, -255.0, 0.0, 255.0, -1.0);
+    ani.add_frame(false, 100.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_color(false, COLOR, 0.0, 0.0, 255.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    ani.add_origin_color(COLOR, 0.0, 0.0, 0.0, 1.0);
+    let ani = wasm_ptr::from_rust(ani);
+    unsafe { &mut *node }.add_ani(ani);
+    (node, get(ani))
+  }
+
+  #[test]
+  fn color_keyframes() {
+    let mut root = Root::new();
+    let (node, ani) = create_color(&mut root);
+    ani.play();
+    tick(&mut root, 0.0);
+    assert_eq!(color(node), vec![255.0, 0.0, 0.0, 1.0]);
+    // 预乘alpha，透明的一端不影响rgb
+    tick(&mut root, 50.0);
+    assert_eq!(color(node), vec![255.0, 0.0, 0.0, 0.5]);
+    tick(&mut root, 50.0);
+    assert_eq!(color(node), vec![0.0, 0.0, 255.0, 0.0]);
+    assert!(ani.cancel());
+    assert_eq!(color(node), vec![0.0, 0.0, 0.0, 1.0]);
+  }
+
+  #[test]
+  fn steps_frame() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 2, 0.0, 0.0, LINEAR);
+    // 动画的easing作为帧的默认值
+    ani.set_steps(2, JUMP_START);
+    ani.add_frame(false, 0.0, DEFAULT, 0.0, 0.0, 0.0, 0.0);
+    ani.add_item(false, TRANSLATE_X, 0.0, style_unit::PX, 100.0);
+    ani.add_frame(false, 100.0, DEFAULT, 0.0, 0.0, 0.0, 0.0);
+    ani.add_item(false, TRANSLATE_X, 100.0, style_unit::PX, 0.0);
+    let ani = wasm_ptr::from_rust(ani);
+    unsafe { &mut *node }.add_ani(ani);
+    get(ani).play();
+    tick(&mut root, 0.0);
+    assert_eq!(translate_x(node), 50.0);
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 50.0);
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 100.0);
+    // 帧上单独设置
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 1, 0.0, 0.0, LINEAR);
+    ani.add_frame_steps(false, 0.0, 4, JUMP_END);
+    ani.add_item(false, TRANSLATE_X, 0.0, style_unit::PX, 100.0);
+    ani.add_frame(false, 100.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item(false, TRANSLATE_X, 100.0, style_unit::PX, 0.0);
+    let ani = wasm_ptr::from_rust(ani);
+    unsafe { &mut *node }.add_ani(ani);
+    get(ani).play();
+    tick(&mut root, 0.0);
+    assert_eq!(translate_x(node), 0.0);
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 25.0);
+    tick(&mut root, 69.0);
+    assert_eq!(translate_x(node), 75.0);
+  }
+
+  #[test]
+  fn velocity() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    assert_eq!(ani.get_velocity(TRANSLATE_X), 0.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    assert!((ani.get_velocity(TRANSLATE_X) - 1.0).abs() < 1e-9);
+    assert_eq!(ani.get_velocity(COLOR), 0.0);
+    ani.playback_rate = 2.0;
+    assert!((ani.get_velocity(TRANSLATE_X) - 2.0).abs() < 1e-9);
+    ani.play_state = PAUSED;
+    assert_eq!(ani.get_velocity(TRANSLATE_X), 0.0);
+    // 继承速度，开始时为原本速度加上v0，首尾数值不变
+    let (node2, ani2) = create(&mut root, 1.0);
+    ani2.set_velocity(TRANSLATE_X, 2.0);
+    ani2.play();
+    tick(&mut root, 0.0);
+    assert!((ani2.get_velocity(TRANSLATE_X) - 3.0).abs() < 1e-9);
+    assert_eq!(translate_x(node2), 0.0);
+    tick(&mut root, 50.0);
+    assert!((ani2.get_velocity(TRANSLATE_X) - 0.5).abs() < 1e-9);
+    assert_eq!(translate_x(node2), 75.0);
+    tick(&mut root, 50.0);
+    assert_eq!(translate_x(node2), 100.0);
+    assert_eq!(ani2.get_velocity(TRANSLATE_X), 0.0);
+    assert_eq!(translate_x(node), 30.0);
+  }
+
+  #[test]
+  fn event_payload() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let a = get(add_ani(node, 100.0, FORWARDS, 1.0, 2));
+    let b = get(add_ani(node, 100.0, FORWARDS, 1.0, 1));
+    assert_ne!(a.id, b.id);
+    a.play();
+    b.play();
+    let id = a.id as f64;
+    tick(&mut root, 0.0);
+    assert_eq!(events(&root), vec![[EVENT_FRAME as f64, id, 0.0, 0.0], [EVENT_BEGIN as f64, id, 0.0, 0.0],
+      [EVENT_FRAME as f64, b.id as f64, 0.0, 0.0], [EVENT_BEGIN as f64, b.id as f64, 0.0, 0.0]]);
+    b.cancel();
+    tick(&mut root, 150.0);
+    assert_eq!(events(&root), vec![[EVENT_FRAME as f64, id, 1.0, 150.0], [EVENT_ITERATION as f64, id, 1.0, 150.0]]);
+    tick(&mut root, 60.0);
+    assert_eq!(events(&root), vec![[EVENT_FRAME as f64, id, 1.0, 210.0], [EVENT_END as f64, id, 1.0, 210.0],
+      [EVENT_FINISH as f64, id, 1.0, 210.0]]);
+    // 每帧开始时清空
+    root.before(0.0);
+    assert!(kinds(&root).is_empty());
+  }
+
+  #[test]
+  fn reverse_paused() {
+    let mut root = Root::new();
+    let (node, ani) = create(&mut root, 1.0);
+    ani.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    ani.goto_stop(60.0, 100.0);
+    assert_eq!(ani.play_state, PAUSED);
+    ani.reverse();
+    assert_eq!(ani.play_state, RUNNING);
+    tick(&mut root, 10.0);
+    assert_eq!(translate_x(node), 50.0);
+  }
+}
@@ -0,0 +1,210 @@
+use std::f64;
+use crate::animation::{LINEAR_RGB, OKLAB, OKLCH, HUE_LONGER, HUE_INCREASING, HUE_DECREASING};
+
+const CHROMA_MIN: f64 = 0.000001;
+
+fn srgb_to_linear(v: f64) -> f64 {
+  let v = v / 255.0;
+  if v <= 0.04045 {
+    v / 12.92
+  } else {
+    ((v + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+fn linear_to_srgb(v: f64) -> f64 {
+  let v = if v <= 0.0031308 {
+    v * 12.92
+  } else {
+    1.055 * v.powf(1.0 / 2.4) - 0.055
+  };
+  v * 255.0
+}
+
+fn linear_to_oklab(r: f64, g: f64, b: f64) -> [f64; 3] {
+  let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+  let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+  let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+  [
+    0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+    1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+    0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+  ]
+}
+
+fn oklab_to_linear(l: f64, a: f64, b: f64) -> [f64; 3] {
+  let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+  let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+  let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+  let l = l_ * l_ * l_;
+  let m = m_ * m_ * m_;
+  let s = s_ * s_ * s_;
+  [
+    4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+    -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+    -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+  ]
+}
+
+// srgb的0~255转换到插值空间，oklch的hue为角度
+fn to_space(c: &[f64], space: u8) -> [f64; 3] {
+  if space == LINEAR_RGB || space == OKLAB || space == OKLCH {
+    let r = srgb_to_linear(c[0]);
+    let g = srgb_to_linear(c[1]);
+    let b = srgb_to_linear(c[2]);
+    if space == LINEAR_RGB {
+      return [r, g, b]
+    }
+    let lab = linear_to_oklab(r, g, b);
+    if space == OKLAB {
+      return lab
+    }
+    let h = lab[2].atan2(lab[1]).to_degrees();
+    return [lab[0], (lab[1] * lab[1] + lab[2] * lab[2]).sqrt(), if h < 0.0 { h + 360.0 } else { h }]
+  }
+  [c[0], c[1], c[2]]
+}
+
+fn from_space(c: &[f64; 3], space: u8) -> [f64; 3] {
+  if space == LINEAR_RGB || space == OKLAB || space == OKLCH {
+    let rgb = if space == LINEAR_RGB {
+      *c
+    } else if space == OKLAB {
+      oklab_to_linear(c[0], c[1], c[2])
+    } else {
+      let h = c[2].to_radians();
+      oklab_to_linear(c[0], c[1] * h.cos(), c[1] * h.sin())
+    };
+    return [linear_to_srgb(rgb[0]), linear_to_srgb(rgb[1]), linear_to_srgb(rgb[2])]
+  }
+  *c
+}
+
+// 同css color4的hue插值方法，调整h1和h2使得二者的差值符合方向
+fn fix_hue(h1: &mut f64, h2: &mut f64, hue: u8) -> () {
+  let d = *h2 - *h1;
+  if hue == HUE_LONGER {
+    if d > 0.0 && d < 180.0 {
+      *h1 += 360.0;
+    } else if d > -180.0 && d <= 0.0 {
+      *h2 += 360.0;
+    }
+  } else if hue == HUE_INCREASING {
+    if d < 0.0 {
+      *h2 += 360.0;
+    }
+  } else if hue == HUE_DECREASING {
+    if d > 0.0 {
+      *h1 += 360.0;
+    }
+  } else {
+    if d > 180.0 {
+      *h1 += 360.0;
+    } else if d < -180.0 {
+      *h2 += 360.0;
+    }
+  }
+}
+
+// 起始色c和差值d，均为rgba，rgb范围0~255，a范围0~1
+// 同css，在space空间中预乘alpha后插值再还原，避免和透明色过渡时中间出现发黑的rgb
+pub fn interpolate(c: &[f64; 4], d: &[f64; 4], percent: f64, space: u8, hue: u8) -> [f64; 4] {
+  let e = [c[0] + d[0], c[1] + d[1], c[2] + d[2]];
+  let mut v1 = to_space(c, space);
+  let mut v2 = to_space(&e, space);
+  let is_lch = space == OKLCH;
+  if is_lch {
+    // 无彩度时hue无意义，取另一端的
+    if v1[1] < CHROMA_MIN {
+      v1[2] = v2[2];
+    } else if v2[1] < CHROMA_MIN {
+      v2[2] = v1[2];
+    } else {
+      let (mut h1, mut h2) = (v1[2], v2[2]);
+      fix_hue(&mut h1, &mut h2, hue);
+      v1[2] = h1;
+      v2[2] = h2;
+    }
+  }
+  let a1 = c[3];
+  let a2 = c[3] + d[3];
+  let a = a1 + d[3] * percent;
+  let mut v = [0.0; 3];
+  for i in 0..3 {
+    // 全透明时预乘结果无意义，直接插值，hue不参与预乘
+    v[i] = if a <= 0.0 || is_lch && i == 2 {
+      v1[i] + (v2[i] - v1[i]) * percent
+    } else {
+      let p1 = v1[i] * a1;
+      let p2 = v2[i] * a2;
+      (p1 + (p2 - p1) * percent) / a
+    };
+  }
+  if is_lch {
+    v[1] = f64::max(0.0, v[1]);
+    v[2] %= 360.0;
+  }
+  let rgb = from_space(&v, space);
+  let mut res = [0.0; 4];
+  // 超出srgb色域的直接截断
+  for (r, v) in res.iter_mut().zip(rgb.iter()) {
+    *r = v.clamp(0.0, 255.0);
+  }
+  // easing可能超出0~1，alpha需限制
+  res[3] = a.clamp(0.0, 1.0);
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::animation::animation::{SRGB, HUE_SHORTER};
+
+  fn close(a: &[f64], b: &[f64], e: f64) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < e)
+  }
+
+  #[test]
+  fn color_space() {
+    let red = [255.0, 0.0, 0.0, 1.0];
+    let d = [-255.0, 0.0, 255.0, 0.0];
+    assert!(close(&interpolate(&red, &d, 0.5, SRGB, HUE_SHORTER), &[127.5, 0.0, 127.5, 1.0], 1e-9));
+    // 线性空间的中点转回srgb更亮
+    let c = interpolate(&red, &d, 0.5, LINEAR_RGB, HUE_SHORTER);
+    assert!(close(&c, &[187.5, 0.0, 187.5, 1.0], 0.5));
+    // 各空间首尾不变，oklab矩阵有精度误差
+    for space in [SRGB, LINEAR_RGB, OKLAB, OKLCH] {
+      assert!(close(&interpolate(&red, &d, 0.0, space, HUE_SHORTER), &red, 0.01));
+      assert!(close(&interpolate(&red, &d, 1.0, space, HUE_SHORTER), &[0.0, 0.0, 255.0, 1.0], 0.01));
+    }
+    let lab = to_space(&[12.0, 200.0, 90.0], OKLAB);
+    assert!(close(&from_space(&lab, OKLAB), &[12.0, 200.0, 90.0], 0.01));
+    let lch = to_space(&[12.0, 200.0, 90.0], OKLCH);
+    assert!(close(&from_space(&lch, OKLCH), &[12.0, 200.0, 90.0], 0.01));
+  }
+
+  #[test]
+  fn hue_mode() {
+    let cases = [
+      (HUE_SHORTER, 10.0, 350.0, -20.0),
+      (HUE_SHORTER, 10.0, 30.0, 20.0),
+      (HUE_LONGER, 10.0, 30.0, -340.0),
+      (HUE_LONGER, 10.0, 350.0, 340.0),
+      (HUE_INCREASING, 350.0, 10.0, 20.0),
+      (HUE_INCREASING, 10.0, 350.0, 340.0),
+      (HUE_DECREASING, 10.0, 350.0, -20.0),
+      (HUE_DECREASING, 350.0, 10.0, -340.0),
+    ];
+    for (hue, a, b, d) in cases {
+      let (mut h1, mut h2) = (a, b);
+      fix_hue(&mut h1, &mut h2, hue);
+      assert_eq!(h2 - h1, d);
+    }
+    // 无彩度的一端使用另一端的hue，白到红的中间不会偏色到其它hue
+    let white = [255.0, 255.0, 255.0, 1.0];
+    let c = interpolate(&white, &[0.0, -255.0, -255.0, 0.0], 0.5, OKLCH, HUE_LONGER);
+    let h = to_space(&c, OKLCH)[2];
+    assert!((h - to_space(&[255.0, 0.0, 0.0], OKLCH)[2]).abs() < 1.0);
+    assert!(c[0] > c[1] && (c[1] - c[2]).abs() < 20.0);
+  }
+}
@@ -1,10 +1,17 @@
 use lazy_static::lazy_static;
+use crate::animation::{JUMP_START, JUMP_NONE, JUMP_BOTH};
 
 const NEWTON_ITERATIONS: usize = 4;
 const NEWTON_MIN_SLOPE: f64 = 0.001;
 const SUBDIVISION_PRECISION: f64 = 0.0000001;
 const SUBDIVISION_MAX_ITERATIONS: usize = 10;
 
+// spring的静止阈值，位移和速度均按起止距离为1归一化，速度单位为每秒
+pub const REST_DISPLACEMENT: f64 = 0.001;
+pub const REST_VELOCITY: f64 = 0.01;
+const SPRING_STEP: f64 = 1.0 / 120.0;
+const SPRING_MAX_TIME: f64 = 60.0;
+
 const K_SPLINE_TABLE_SIZE: usize = 11;
 const K_SAMPLE_STEP_SIZE: f64= 1.0 / (K_SPLINE_TABLE_SIZE as f64 - 1.0);
 
@@ -25,7 +32,7 @@ fn cal_c_bezier(t: f64, a1: f64, a2: f64) -> f64 {
 }
 
 fn get_slop(t: f64, a1: f64, a2: f64) -> f64 {
-  3.0 * a(a1, a2) * t * t + 2.0 * b(a2, a2) * t + c(a1)
+  3.0 * a(a1, a2) * t * t + 2.0 * b(a1, a2) * t + c(a1)
 }
 
 fn binary_subdivide(x: f64, mut a: f64, mut b: f64, x1: f64, x2: f64) -> f64 {
@@ -65,10 +72,6 @@ fn newton_raphson_iterate(x: f64, mut gt: f64, x1: f64, x2: f64) -> f64 {
   gt
 }
 
-fn linear_easing(x: f64) -> f64 {
-  x
-}
-
 pub struct Bezier {
   x1: f64,
   y1: f64,
@@ -125,10 +128,251 @@ impl Bezier {
     let v = self.get_t_for_x(x);
     cal_c_bezier(v, self.y1, self.y2)
   }
+
+  // 输出对输入的导数dy/dx
+  pub fn derivative(&self, x: f64) -> f64 {
+    let t = if x <= 0.0 {
+      0.0
+    } else if x >= 1.0 {
+      1.0
+    } else {
+      self.get_t_for_x(x)
+    };
+    let dx = get_slop(t, self.x1, self.x2);
+    if dx == 0.0 {
+      return 0.0
+    }
+    get_slop(t, self.y1, self.y2) / dx
+  }
+}
+
+// css的steps(n, position)，不考虑before flag
+pub struct Steps {
+  n: usize,
+  position: u8,
+}
+
+impl Steps {
+  pub fn new(n: usize, position: u8) -> Steps {
+    Steps {
+      n: if n == 0 { 1 } else { n },
+      position,
+    }
+  }
+
+  pub fn timing_function(&self, x: f64) -> f64 {
+    let n = self.n as f64;
+    let mut current = (x * n).floor();
+    if self.position == JUMP_START || self.position == JUMP_BOTH {
+      current += 1.0;
+    }
+    // 跳跃次数，jump-none至少需2步
+    let jumps = if self.position == JUMP_NONE {
+      f64::max(1.0, n - 1.0)
+    } else if self.position == JUMP_BOTH {
+      n + 1.0
+    } else {
+      n
+    };
+    if x >= 0.0 && current < 0.0 {
+      current = 0.0;
+    }
+    if x <= 1.0 && current > jumps {
+      current = jumps;
+    }
+    current / jumps
+  }
+}
+
+// css easing2的linear()，输入输出对应的点之间线性插值
+#[derive(Clone)]
+pub struct LinearStops {
+  input: Vec<f64>,
+  output: Vec<f64>,
+}
+
+impl LinearStops {
+  // input中NaN表示未指定位置，同css规则补全：首尾默认0和1，小于前面的取前面最大值，中间缺失的均分
+  pub fn new(mut input: Vec<f64>, output: Vec<f64>) -> LinearStops {
+    let len = output.len();
+    input.resize(len, f64::NAN);
+    if len > 0 {
+      if input[0].is_nan() {
+        input[0] = 0.0;
+      }
+      if len > 1 && input[len - 1].is_nan() {
+        input[len - 1] = f64::max(1.0, input[0]);
+      }
+    }
+    let mut max = f64::NEG_INFINITY;
+    for v in input.iter_mut() {
+      if !v.is_nan() {
+        if *v < max {
+          *v = max;
+        }
+        max = *v;
+      }
+    }
+    let mut i = 0;
+    while i < len {
+      if input[i].is_nan() {
+        let mut j = i + 1;
+        while input[j].is_nan() {
+          j += 1;
+        }
+        let start = input[i - 1];
+        let step = (input[j] - start) / ((j - i + 1) as f64);
+        let mut k = i;
+        while k < j {
+          input[k] = start + step * ((k - i + 1) as f64);
+          k += 1;
+        }
+        i = j;
+      }
+      i += 1;
+    }
+    LinearStops {
+      input,
+      output,
+    }
+  }
+
+  // x所在线段的下标，超出首尾时用首尾两段，相同输入的点取最后一个
+  fn segment(&self, x: f64) -> usize {
+    let len = self.output.len();
+    if x < self.input[0] {
+      0
+    } else if x >= self.input[len - 1] {
+      len - 2
+    } else {
+      let mut i = len - 2;
+      while self.input[i] > x {
+        i -= 1;
+      }
+      i
+    }
+  }
+
+  pub fn timing_function(&self, x: f64) -> f64 {
+    let len = self.output.len();
+    if len == 0 {
+      return x
+    }
+    if len == 1 || x == self.input[len - 1] {
+      return self.output[len - 1]
+    }
+    let i = self.segment(x);
+    let (x1, y1) = (self.input[i], self.output[i]);
+    let (x2, y2) = (self.input[i + 1], self.output[i + 1]);
+    if x1 == x2 {
+      return y2
+    }
+    y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+  }
+
+  pub fn derivative(&self, x: f64) -> f64 {
+    let len = self.output.len();
+    if len == 0 {
+      return 1.0
+    }
+    if len == 1 {
+      return 0.0
+    }
+    let i = self.segment(x);
+    let dx = self.input[i + 1] - self.input[i];
+    if dx == 0.0 {
+      return 0.0
+    }
+    (self.output[i + 1] - self.output[i]) / dx
+  }
 }
 
-pub fn bezier(x1: f64, y1: f64, x2: f64, y2: f64) -> Bezier {
-  Bezier::new(x1, y1, x2, y2)
+// 阻尼弹簧，从0运动到1，velocity为初始速度，单位同REST_VELOCITY
+#[derive(Clone)]
+pub struct Spring {
+  w0: f64,
+  zeta: f64,
+  velocity: f64,
+  pub duration: f64, // 达到静止阈值的时间，单位ms
+}
+
+impl Spring {
+  pub fn new(mass: f64, stiffness: f64, damping: f64, velocity: f64,
+             rest_displacement: f64, rest_velocity: f64) -> Spring {
+    let mass = if mass > 0.0 { mass } else { 1.0 };
+    let stiffness = if stiffness > 0.0 { stiffness } else { 100.0 };
+    let damping = f64::max(0.0, damping);
+    let mut spring = Spring {
+      w0: (stiffness / mass).sqrt(),
+      zeta: damping / (2.0 * (stiffness * mass).sqrt()),
+      velocity,
+      duration: 0.0,
+    };
+    spring.duration = spring.cal_duration(rest_displacement, rest_velocity);
+    spring
+  }
+
+  // t秒时距终点的位移（终点为0，起点为-1）和速度
+  pub fn solve(&self, t: f64) -> (f64, f64) {
+    let w0 = self.w0;
+    let zeta = self.zeta;
+    let v0 = self.velocity;
+    if zeta < 1.0 {
+      let wd = w0 * (1.0 - zeta * zeta).sqrt();
+      let a = -1.0;
+      let b = (v0 + zeta * w0 * a) / wd;
+      let e = (-zeta * w0 * t).exp();
+      let (sin, cos) = (wd * t).sin_cos();
+      let x = e * (a * cos + b * sin);
+      let v = -zeta * w0 * x + e * (-a * wd * sin + b * wd * cos);
+      (x, v)
+    } else if zeta == 1.0 {
+      let a = -1.0;
+      let b = v0 + w0 * a;
+      let e = (-w0 * t).exp();
+      let x = e * (a + b * t);
+      let v = e * (b - w0 * (a + b * t));
+      (x, v)
+    } else {
+      let d = w0 * (zeta * zeta - 1.0).sqrt();
+      let r1 = -zeta * w0 + d;
+      let r2 = -zeta * w0 - d;
+      let c2 = (v0 + r1) / (r2 - r1);
+      let c1 = -1.0 - c2;
+      let e1 = (r1 * t).exp();
+      let e2 = (r2 * t).exp();
+      (c1 * e1 + c2 * e2, c1 * r1 * e1 + c2 * r2 * e2)
+    }
+  }
+
+  fn cal_duration(&self, rest_displacement: f64, rest_velocity: f64) -> f64 {
+    let mut t = 0.0;
+    while t < SPRING_MAX_TIME {
+      t += SPRING_STEP;
+      let (x, v) = self.solve(t);
+      if x.abs() < rest_displacement && v.abs() < rest_velocity {
+        break;
+      }
+    }
+    t * 1000.0
+  }
+
+  // x为0~1对应的是静止前的全部时间
+  pub fn timing_function(&self, x: f64) -> f64 {
+    if x >= 1.0 {
+      return 1.0
+    }
+    let (d, _) = self.solve(x * self.duration * 0.001);
+    1.0 + d
+  }
+
+  pub fn derivative(&self, x: f64) -> f64 {
+    if x >= 1.0 {
+      return 0.0
+    }
+    let (_, v) = self.solve(x * self.duration * 0.001);
+    v * self.duration * 0.001
+  }
 }
 
 pub enum BezierEnum {
@@ -138,6 +382,76 @@ pub enum BezierEnum {
   Ease,
   EaseInOut,
   Custom(Bezier),
+  Steps(Steps),
+  LinearStops(LinearStops),
+  Spring(Spring),
+}
+
+impl BezierEnum {
+  pub fn timing_function(&self, x: f64) -> f64 {
+    match self {
+      BezierEnum::Ease => {
+        EASE.timing_function(x)
+      }
+      BezierEnum::EaseIn => {
+        EASE_IN.timing_function(x)
+      }
+      BezierEnum::EaseOut => {
+        EASE_OUT.timing_function(x)
+      }
+      BezierEnum::EaseInOut => {
+        EASE_IN_OUT.timing_function(x)
+      }
+      BezierEnum::Custom(b) => {
+        b.timing_function(x)
+      }
+      BezierEnum::Steps(s) => {
+        s.timing_function(x)
+      }
+      BezierEnum::LinearStops(l) => {
+        l.timing_function(x)
+      }
+      BezierEnum::Spring(s) => {
+        s.timing_function(x)
+      }
+      _ => {
+        x
+      }
+    }
+  }
+
+  // 即时速度需要easing的导数
+  pub fn derivative(&self, x: f64) -> f64 {
+    match self {
+      BezierEnum::Ease => {
+        EASE.derivative(x)
+      }
+      BezierEnum::EaseIn => {
+        EASE_IN.derivative(x)
+      }
+      BezierEnum::EaseOut => {
+        EASE_OUT.derivative(x)
+      }
+      BezierEnum::EaseInOut => {
+        EASE_IN_OUT.derivative(x)
+      }
+      BezierEnum::Custom(b) => {
+        b.derivative(x)
+      }
+      BezierEnum::Steps(_) => {
+        0.0
+      }
+      BezierEnum::LinearStops(l) => {
+        l.derivative(x)
+      }
+      BezierEnum::Spring(s) => {
+        s.derivative(x)
+      }
+      _ => {
+        1.0
+      }
+    }
+  }
 }
 
 lazy_static! {
@@ -147,3 +461,88 @@ lazy_static! {
   pub static ref EASE: Bezier = Bezier::new(0.25, 0.1, 0.25, 1.0);
   pub static ref EASE_IN_OUT: Bezier = Bezier::new(0.42, 0.0, 0.58, 1.0);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::animation::animation::JUMP_END;
+
+  fn close(a: f64, b: f64, e: f64) -> bool {
+    (a - b).abs() < e
+  }
+
+  #[test]
+  fn spring() {
+    // 欠阻尼、临界阻尼、过阻尼
+    for damping in [10.0, 20.0, 40.0] {
+      let s = Spring::new(1.0, 100.0, damping, 0.0, REST_DISPLACEMENT, REST_VELOCITY);
+      assert!(s.duration > 0.0 && s.duration < SPRING_MAX_TIME * 1000.0);
+      assert!(close(s.timing_function(0.0), 0.0, 1e-9));
+      assert_eq!(s.timing_function(1.0), 1.0);
+      // 静止时已在阈值内
+      assert!(close(s.timing_function(0.9999), 1.0, REST_DISPLACEMENT * 2.0));
+      assert!(close(s.derivative(0.0), 0.0, 1e-9));
+      assert_eq!(s.derivative(1.0), 0.0);
+      // derivative为timing_function对x的导数
+      for x in [0.1, 0.3, 0.6] {
+        let h = 1e-6;
+        let d = (s.timing_function(x + h) - s.timing_function(x - h)) / (2.0 * h);
+        assert!(close(s.derivative(x), d, 1e-3));
+      }
+    }
+    // 初始速度
+    let s = Spring::new(1.0, 100.0, 10.0, 5.0, REST_DISPLACEMENT, REST_VELOCITY);
+    assert!(close(s.derivative(0.0), 5.0 * s.duration * 0.001, 1e-9));
+    // 欠阻尼会越过终点
+    let s = Spring::new(1.0, 100.0, 2.0, 0.0, REST_DISPLACEMENT, REST_VELOCITY);
+    assert!((1..100).any(|i| s.timing_function(i as f64 * 0.01) > 1.0));
+  }
+
+  #[test]
+  fn steps() {
+    let cases = [
+      (4, JUMP_END, [(0.0, 0.0), (0.3, 0.25), (0.99, 0.75), (1.0, 1.0)]),
+      (4, JUMP_START, [(0.0, 0.25), (0.5, 0.75), (0.99, 1.0), (1.0, 1.0)]),
+      (5, JUMP_NONE, [(0.0, 0.0), (0.2, 0.25), (0.99, 1.0), (1.0, 1.0)]),
+      (3, JUMP_BOTH, [(0.0, 0.25), (0.34, 0.5), (0.99, 0.75), (1.0, 1.0)]),
+      // 0步视为1步，jump-none至少跳1次
+      (0, JUMP_END, [(0.0, 0.0), (0.5, 0.0), (0.99, 0.0), (1.0, 1.0)]),
+      (1, JUMP_NONE, [(0.0, 0.0), (0.5, 0.0), (0.99, 0.0), (1.0, 1.0)]),
+    ];
+    for (n, position, list) in cases {
+      let s = Steps::new(n, position);
+      for (x, y) in list {
+        assert_eq!(s.timing_function(x), y);
+      }
+    }
+  }
+
+  #[test]
+  fn linear_stops() {
+    let n = f64::NAN;
+    // 未指定的首尾为0和1，中间均分
+    let l = LinearStops::new(vec![n, n, n, n], vec![0.0, 0.2, 0.6, 1.0]);
+    assert_eq!(l.input, vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+    assert!((l.timing_function(0.5) - 0.4).abs() < 1e-9);
+    assert!((l.derivative(0.5) - 1.2).abs() < 1e-9);
+    // 小于前面的取前面最大值，相同输入取后一个点
+    let l = LinearStops::new(vec![n, 0.8, 0.5, n], vec![0.0, 1.0, 0.5, 1.0]);
+    assert_eq!(l.input, vec![0.0, 0.8, 0.8, 1.0]);
+    assert_eq!(l.timing_function(0.4), 0.5);
+    assert_eq!(l.timing_function(0.8), 0.5);
+    assert_eq!(l.timing_function(0.9), 0.75);
+    // 中间缺失的在两侧已知值之间均分，input长度不足时补齐
+    let l = LinearStops::new(vec![0.0, n, n, 0.9], vec![0.0, 0.1, 0.2, 0.3, 1.0]);
+    assert_eq!(l.input, vec![0.0, 0.3, 0.6, 0.9, 1.0]);
+    // 首个大于1时末尾取首个
+    let l = LinearStops::new(vec![2.0, n], vec![0.0, 1.0]);
+    assert_eq!(l.input, vec![2.0, 2.0]);
+    // 超出首尾时按首尾两段延伸
+    let l = LinearStops::new(vec![], vec![0.0, 1.0]);
+    assert_eq!(l.timing_function(1.5), 1.5);
+    assert_eq!(l.timing_function(-0.5), -0.5);
+    assert_eq!(l.timing_function(1.0), 1.0);
+    assert_eq!(LinearStops::new(vec![], vec![]).timing_function(0.3), 0.3);
+    assert_eq!(LinearStops::new(vec![], vec![0.7]).timing_function(0.3), 0.7);
+  }
+}
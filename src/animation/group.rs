@@ -0,0 +1,404 @@
+use std::f64;
+use wasm_bindgen::prelude::*;
+use crate::{wasm_ptr};
+use crate::animation::{Animation, RUNNING, PAUSED, FINISH};
+
+pub const PARALLEL: u8 = 0;
+pub const SEQUENCE: u8 = 1;
+
+struct GroupItem {
+  animation: *mut Animation,
+  offset: f64, // parallel时为相对group开始的时间，sequence时为相对前一个结束的间隔，可为负数重叠
+  ended: bool, // 本轮已结束，不再驱动
+  active: bool, // 本帧处于自身时间范围内或跨越了开头结尾，after时才触发事件
+  pos: f64, // 上次计算时相对自身开始的group时间，判断是否跨越开头结尾
+}
+
+// 共享时间轴的动画组，子动画的时间由group统一计算，不再各自累加
+#[wasm_bindgen]
+pub struct AnimationGroup {
+  list: Vec<GroupItem>,
+  starts: Vec<f64>, // 各子动画在group时间轴上的开始时间，列表变化时预计算
+  dur: f64,
+  pub mode: u8,
+  pub iterations: usize,
+  pub playback_rate: f64,
+  pub current_time: f64,
+  pub play_count: usize,
+  pub play_state: u8,
+  finished: bool,
+}
+
+#[wasm_bindgen]
+impl AnimationGroup {
+  pub fn new(mode: u8, iterations: usize, playback_rate: f64) -> AnimationGroup {
+    AnimationGroup {
+      list: Vec::new(),
+      starts: Vec::new(),
+      dur: 0.0,
+      // 未知的mode视为parallel
+      mode: if mode == SEQUENCE { SEQUENCE } else { PARALLEL },
+      iterations,
+      playback_rate,
+      current_time: 0.0,
+      play_count: 0,
+      play_state: 0,
+      finished: false,
+    }
+  }
+
+  // 子动画需已添加到node上，之后由group驱动
+  pub fn add(&mut self, animation: *mut Animation, offset: f64) -> () {
+    let animation = wasm_ptr::transform_mut(animation);
+    let ani = unsafe { &mut *animation };
+    ani.in_group = true;
+    self.list.push(GroupItem {
+      animation,
+      offset,
+      ended: false,
+      active: false,
+      pos: f64::NEG_INFINITY,
+    });
+    self.cal_starts();
+  }
+
+  pub fn remove(&mut self, animation: *mut Animation) -> () {
+    let animation = wasm_ptr::transform_mut(animation);
+    for item in self.list.iter().filter(|x| x.animation == animation) {
+      let ani = unsafe { &mut *item.animation };
+      ani.in_group = false;
+    }
+    self.list.retain(|x| x.animation != animation);
+    self.cal_starts();
+  }
+
+  // 单轮时长，即所有子动画中最晚结束的时间
+  pub fn duration(&self) -> f64 {
+    self.dur
+  }
+
+  // playback_rate为负时从结尾开始倒放
+  pub fn play(&mut self) -> () {
+    // 子动画的时长可能在添加后被修改
+    self.cal_starts();
+    if self.playback_rate < 0.0 {
+      self.current_time = self.duration() * (self.iterations as f64);
+      self.play_count = if self.iterations > 0 { self.iterations - 1 } else { 0 };
+    } else {
+      self.current_time = 0.0;
+      self.play_count = 0;
+    }
+    self.play_state = RUNNING;
+    self.finished = false;
+    self.reset();
+  }
+
+  pub fn pause(&mut self) -> () {
+    self.play_state = PAUSED;
+  }
+
+  pub fn resume(&mut self) -> () {
+    if self.play_state == PAUSED {
+      self.play_state = RUNNING;
+    }
+  }
+
+  // 跳到group时间轴上的v，保持播放状态，返回是否有子动画需要刷新
+  pub fn seek(&mut self, v: f64) -> bool {
+    self.current_time = v;
+    self.finished = false;
+    let res = self.cal_children(false);
+    // 跳转不触发事件
+    for item in self.list.iter_mut() {
+      let ani = unsafe { &mut *item.animation };
+      ani.drop_events();
+      item.active = false;
+    }
+    res
+  }
+
+  pub fn before(&mut self, diff: f64) -> bool {
+    if self.play_state != RUNNING {
+      return false
+    }
+    self.current_time += diff * self.playback_rate;
+    self.cal_children(self.playback_rate >= 0.0)
+  }
+
+  // 子动画依次触发自身事件，返回group是否finish
+  pub fn after(&mut self) -> bool {
+    if self.play_state != RUNNING {
+      return false
+    }
+    for item in self.list.iter() {
+      let ani = unsafe { &mut *item.animation };
+      // 不在范围内时产生的事件标识直接丢弃，防止之后错误触发
+      if item.active {
+        ani.after();
+      } else {
+        ani.drop_events();
+      }
+    }
+    if self.finished {
+      self.finished = false;
+      self.play_state = FINISH;
+      return true
+    }
+    false
+  }
+
+  // 子动画在group时间轴上占用的时长，同Animation，playback_rate为0时视为0
+  fn span(ani: &Animation) -> f64 {
+    if ani.playback_rate != 0.0 {
+      ani.active_duration() / ani.playback_rate
+    } else {
+      0.0
+    }
+  }
+
+  fn cal_starts(&mut self) -> () {
+    self.starts.clear();
+    self.dur = 0.0;
+    let mut prev = 0.0;
+    for item in self.list.iter() {
+      let ani = unsafe { & *item.animation };
+      let span = AnimationGroup::span(ani);
+      let start = if self.mode == SEQUENCE { prev + item.offset } else { item.offset };
+      prev = start + span;
+      self.starts.push(start);
+      self.dur = f64::max(self.dur, prev);
+    }
+  }
+
+  fn reset(&mut self) -> () {
+    // 倒放从结尾之后开始
+    let pos = if self.playback_rate < 0.0 { f64::INFINITY } else { f64::NEG_INFINITY };
+    for item in self.list.iter_mut() {
+      let ani = unsafe { &mut *item.animation };
+      ani.play();
+      item.ended = false;
+      item.pos = pos;
+    }
+  }
+
+  // forward为正常播放向前推进，跳转和倒放时为false
+  fn cal_children(&mut self, forward: bool) -> bool {
+    let dur = self.duration();
+    if dur <= 0.0 || self.iterations == 0 {
+      return false
+    }
+    let mut time = f64::max(0.0, self.current_time);
+    let mut play_count = (time / dur) as usize;
+    // 最后一轮结束停在末尾，倒放时到达开头结束
+    if play_count > self.iterations - 1 {
+      play_count = self.iterations - 1;
+      time = dur * (self.iterations as f64);
+      if self.playback_rate >= 0.0 {
+        self.finished = true;
+      }
+    }
+    if self.playback_rate < 0.0 && self.current_time <= 0.0 {
+      self.finished = true;
+    }
+    // 轮次变化时子动画从头计算
+    if play_count != self.play_count {
+      self.play_count = play_count;
+      self.reset();
+    }
+    // 倒放越过开头时子动画均处于开始之前
+    let local = if self.current_time < 0.0 { self.current_time } else { time - dur * (play_count as f64) };
+    let mut res = false;
+    for (item, &start) in self.list.iter_mut().zip(self.starts.iter()) {
+      let ani = unsafe { &mut *item.animation };
+      let span = AnimationGroup::span(ani);
+      let pos = local - start;
+      let last = item.pos;
+      item.pos = pos;
+      item.active = (pos >= 0.0 && pos <= span) || (last < 0.0) != (pos < 0.0) || (last > span) != (pos > span);
+      let t = f64::min(pos, span) * ani.playback_rate;
+      let changed = if forward && t >= ani.current_time {
+        if item.ended {
+          continue;
+        }
+        let diff = t - ani.current_time;
+        ani.current_time = t;
+        ani.update(diff)
+      } else {
+        // 时间未变化，或已处于开始之前无需重复恢复
+        if (item.ended && t == ani.current_time) || (pos < 0.0 && ani.current_time < 0.0) {
+          ani.current_time = t;
+          continue;
+        }
+        // 跳转或倒退时缓存的帧和状态标识均失效，按绝对时间重新计算
+        ani.reset_state();
+        item.ended = false;
+        let diff = t - ani.current_time;
+        ani.current_time = t;
+        // 回到开始之前同倒放，根据fill保留首帧或恢复origin
+        if pos < 0.0 {
+          ani.cal_start()
+        } else {
+          ani.update(diff)
+        }
+      };
+      if changed {
+        res = true;
+        let node = unsafe { &mut *ani.get_node() };
+        node.cal_trans(ani);
+      }
+      if ani.finished && pos >= 0.0 {
+        item.ended = true;
+      }
+    }
+    res
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::wasm_ptr;
+  use crate::node::Root;
+  use crate::test_util::{get, add_node, add_ani, translate_x, events, kinds, tick};
+  use crate::animation::{NONE, FORWARDS};
+  use crate::animation::animation::{EVENT_FRAME, EVENT_BEGIN, EVENT_END, EVENT_FINISH};
+
+  fn id(ani: *mut Animation) -> f64 {
+    get(ani).id as f64
+  }
+
+  // 添加到root上由root驱动
+  fn create(root: &mut Root, mode: u8, iterations: usize) -> &'static mut AnimationGroup {
+    let group = wasm_ptr::from_rust(AnimationGroup::new(mode, iterations, 1.0));
+    root.add_group(group);
+    get(group)
+  }
+
+  // 指定动画的事件类型
+  fn kinds_of(root: &Root, ani: *mut Animation) -> Vec<u8> {
+    events(root).iter().filter(|e| e[1] == id(ani)).map(|e| e[0] as u8).collect()
+  }
+
+  #[test]
+  fn sequence_starts() {
+    let mut root = Root::new();
+    let a = add_ani(add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0), 100.0, NONE, 1.0, 1);
+    let b = add_ani(add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0), 200.0, NONE, 2.0, 1);
+    let c = add_ani(add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0), 300.0, NONE, 0.0, 1);
+    let mut group = AnimationGroup::new(SEQUENCE, 1, 1.0);
+    group.add(a, 0.0);
+    group.add(b, 50.0);
+    group.add(c, -10.0);
+    // b从150开始占100，c的rate为0不占用时长
+    assert_eq!(group.starts, vec![0.0, 150.0, 240.0]);
+    assert_eq!(group.duration(), 250.0);
+    group.remove(b);
+    assert!(!get(b).in_group);
+    assert_eq!(group.starts, vec![0.0, 90.0]);
+    assert_eq!(group.duration(), 100.0);
+  }
+
+  #[test]
+  fn parallel_events() {
+    let mut root = Root::new();
+    let a = add_ani(add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0), 100.0, NONE, 1.0, 1);
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let b = add_ani(node, 100.0, FORWARDS, 1.0, 1);
+    let group = create(&mut root, PARALLEL, 1);
+    group.add(a, 0.0);
+    group.add(b, 50.0);
+    assert_eq!(group.duration(), 150.0);
+    group.play();
+    // b未开始时不触发事件
+    tick(&mut root, 10.0);
+    assert!(events(&root).iter().any(|e| e[1] == id(a)));
+    assert!(kinds_of(&root, b).is_empty());
+    tick(&mut root, 60.0);
+    assert_eq!(kinds_of(&root, b), vec![EVENT_FRAME, EVENT_BEGIN]);
+    assert_eq!(translate_x(node), 20.0);
+    tick(&mut root, 100.0);
+    assert!(kinds_of(&root, b).contains(&EVENT_FINISH));
+    assert_eq!(group.play_state, FINISH);
+    assert_eq!(translate_x(node), 100.0);
+  }
+
+  #[test]
+  fn seek_reverse() {
+    let mut root = Root::new();
+    let na = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let nb = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let a = add_ani(na, 100.0, FORWARDS, 1.0, 2);
+    let b = add_ani(nb, 100.0, FORWARDS, 1.0, 2);
+    let group = create(&mut root, SEQUENCE, 1);
+    group.add(a, 0.0);
+    group.add(b, 0.0);
+    group.play();
+    group.seek(50.0);
+    assert_eq!(translate_x(na), 50.0);
+    assert_eq!(translate_x(nb), 5.0);
+    group.seek(350.0);
+    assert_eq!(translate_x(na), 100.0);
+    assert_eq!(translate_x(nb), 50.0);
+    group.playback_rate = -1.0;
+    tick(&mut root, 100.0);
+    assert_eq!(translate_x(na), 100.0);
+    assert_eq!(translate_x(nb), 50.0);
+    // 跳转的结束事件不会延迟触发
+    assert!(kinds_of(&root, a).is_empty());
+    tick(&mut root, 100.0);
+    assert_eq!(translate_x(na), 50.0);
+    // b倒退到开始之前恢复origin
+    assert_eq!(translate_x(nb), 5.0);
+    assert_eq!(kinds_of(&root, b), vec![EVENT_FRAME, EVENT_END, EVENT_FINISH]);
+    assert!(!kinds_of(&root, a).contains(&EVENT_FINISH));
+    tick(&mut root, 100.0);
+    assert!(kinds_of(&root, b).is_empty());
+    assert_eq!(translate_x(na), 50.0);
+    tick(&mut root, 100.0);
+    assert_eq!(translate_x(na), 5.0);
+    assert!(kinds_of(&root, a).contains(&EVENT_FINISH));
+    assert_eq!(group.play_state, FINISH);
+  }
+
+  #[test]
+  fn iterations() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let a = add_ani(node, 100.0, FORWARDS, 1.0, 1);
+    let group = create(&mut root, PARALLEL, 2);
+    group.add(a, 0.0);
+    group.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 50.0);
+    assert_eq!(translate_x(node), 50.0);
+    // 第二轮子动画重新开始
+    tick(&mut root, 100.0);
+    assert_eq!(group.play_count, 1);
+    assert_eq!(translate_x(node), 50.0);
+    assert!(kinds_of(&root, a).contains(&EVENT_BEGIN));
+    tick(&mut root, 60.0);
+    assert_eq!(translate_x(node), 100.0);
+    assert!(kinds(&root).contains(&EVENT_FINISH));
+    assert_eq!(group.play_state, FINISH);
+  }
+
+  #[test]
+  fn pause() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    let a = add_ani(node, 100.0, FORWARDS, 1.0, 1);
+    let group = create(&mut root, PARALLEL, 1);
+    group.add(a, 0.0);
+    group.play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 30.0);
+    group.pause();
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 30.0);
+    assert!(kinds(&root).is_empty());
+    group.resume();
+    tick(&mut root, 30.0);
+    assert_eq!(translate_x(node), 60.0);
+    assert_eq!(group.current_time, 60.0);
+  }
+}
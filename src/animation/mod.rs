@@ -1,13 +1,22 @@
 mod animation;
 mod easing;
+mod color;
+mod group;
 
 pub use animation::Animation;
+pub(crate) use animation::Transition;
+pub use group::AnimationGroup;
 
 pub use animation::LINEAR;
 pub use animation::EASE_IN;
 pub use animation::EASE_OUT;
 pub use animation::EASE;
 pub use animation::EASE_IN_OUT;
+pub use animation::EASE_CUSTOM;
+
+pub use animation::JUMP_START;
+pub use animation::JUMP_NONE;
+pub use animation::JUMP_BOTH;
 
 pub use animation::NORMAL;
 pub use animation::REVERSE;
@@ -19,10 +28,25 @@ pub use animation::FORWARDS;
 pub use animation::BACKWARDS;
 pub use animation::BOTH;
 
+pub use animation::REPLACE;
+pub use animation::ADD;
+pub use animation::ACCUMULATE;
+
 pub use animation::IDLE;
 pub use animation::RUNNING;
 pub use animation::PAUSED;
 pub use animation::FINISH;
 
+pub use animation::LINEAR_RGB;
+pub use animation::OKLAB;
+pub use animation::OKLCH;
+
+pub use animation::HUE_LONGER;
+pub use animation::HUE_INCREASING;
+pub use animation::HUE_DECREASING;
+
 pub use easing::Bezier;
 pub use easing::BezierEnum;
+pub use easing::Steps;
+pub use easing::LinearStops;
+pub use easing::Spring;
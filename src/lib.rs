@@ -28,6 +28,13 @@ mod wasm_ptr {
   pub fn transform<T>(addr: *const T) -> *const T {
     wasm_ptr_transform!(addr)
   }
+
+  // 测试中模拟js侧持有的对象指针，供transform_mut解引用
+  #[cfg(test)]
+  pub fn from_rust<T>(v: T) -> *mut T {
+    use wasm_bindgen::__rt::WasmRefCell;
+    Box::into_raw(Box::new(WasmRefCell::new(v))) as *mut T
+  }
 }
 
 
@@ -61,3 +68,5 @@ mod node;
 mod animation;
 mod refresh;
 mod math;
+#[cfg(test)]
+mod test_util;
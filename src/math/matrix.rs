@@ -1,6 +1,9 @@
 use std::f64::consts::PI;
 use wasm_bindgen::prelude::*;
 
+// 透视裁剪面，w小于此值的顶点视为在视点背后
+const W_MIN: f64 = 0.000001;
+
 pub fn identity() -> [f64; 16] {
   [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]
 }
@@ -211,8 +214,8 @@ pub fn multiply_scale_z(m: &mut [f64; 16], v: f64) -> () {
   m[11] *= v;
 }
 
-pub fn tfo_multiply(m: &mut [f64; 16], x: f64, y: f64) -> () {
-  if x == 0.0 && y == 0.0 {
+pub fn tfo_multiply(m: &mut [f64; 16], x: f64, y: f64, z: f64) -> () {
+  if x == 0.0 && y == 0.0 && z == 0.0 {
     return
   }
   let d = m[3];
@@ -221,22 +224,26 @@ pub fn tfo_multiply(m: &mut [f64; 16], x: f64, y: f64) -> () {
   let p = m[15];
   m[0] += d * x;
   m[1] += d * y;
+  m[2] += d * z;
   m[4] += h * x;
   m[5] += h * y;
+  m[6] += h * z;
   m[8] += l * x;
   m[9] += l * y;
+  m[10] += l * z;
   m[12] += p * x;
   m[13] += p * y;
+  m[14] += p * z;
 }
 
-pub fn multiply_tfo(m: &mut [f64; 16], x: f64, y: f64) -> () {
-  if x == 0.0 && y == 0.0 {
+pub fn multiply_tfo(m: &mut [f64; 16], x: f64, y: f64, z: f64) -> () {
+  if x == 0.0 && y == 0.0 && z == 0.0 {
     return
   }
-  m[12] += m[0] * x + m[4] * y;
-  m[13] += m[1] * x + m[5] * y;
-  m[14] += m[2] * x + m[6] * y;
-  m[15] += m[3] * x + m[7] * y;
+  m[12] += m[0] * x + m[4] * y + m[8] * z;
+  m[13] += m[1] * x + m[5] * y + m[9] * z;
+  m[14] += m[2] * x + m[6] * y + m[10] * z;
+  m[15] += m[3] * x + m[7] * y + m[11] * z;
 }
 
 pub fn cal_rotate_3d(t: &mut[f64; 16], mut x: f64, mut y: f64, mut z: f64, a: f64) -> () {
@@ -297,82 +304,319 @@ pub fn cal_rotate_3d(t: &mut[f64; 16], mut x: f64, mut y: f64, mut z: f64, a: f6
   }
 }
 
+// 2d仿射矩阵只有abcdef有效，可以走快速求逆
+pub fn is_2d(m: &[f64; 16]) -> bool {
+  m[2] == 0.0
+    && m[3] == 0.0
+    && m[6] == 0.0
+    && m[7] == 0.0
+    && m[8] == 0.0
+    && m[9] == 0.0
+    && m[10] == 1.0
+    && m[11] == 0.0
+    && m[14] == 0.0
+    && m[15] == 1.0
+}
+
+// 求逆矩阵，不可逆（如scale为0）时返回None
+pub fn inverse(m: &[f64; 16]) -> Option<[f64; 16]> {
+  if is_e(m) {
+    return Some(identity())
+  }
+  if is_2d(m) {
+    let a = m[0];
+    let b = m[1];
+    let c = m[4];
+    let d = m[5];
+    let e = m[12];
+    let f = m[13];
+    let det = a * d - b * c;
+    if det == 0.0 || !det.is_finite() {
+      return None
+    }
+    let r = 1.0 / det;
+    let mut t = identity();
+    t[0] = d * r;
+    t[1] = -b * r;
+    t[4] = -c * r;
+    t[5] = a * r;
+    t[12] = (c * f - d * e) * r;
+    t[13] = (b * e - a * f) * r;
+    return Some(t)
+  }
+  let mut t = [0.0; 16];
+  t[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15]
+    + m[9] * m[7] * m[14] + m[13] * m[6] * m[11] - m[13] * m[7] * m[10];
+  t[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15]
+    - m[8] * m[7] * m[14] - m[12] * m[6] * m[11] + m[12] * m[7] * m[10];
+  t[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15]
+    + m[8] * m[7] * m[13] + m[12] * m[5] * m[11] - m[12] * m[7] * m[9];
+  t[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14]
+    - m[8] * m[6] * m[13] - m[12] * m[5] * m[10] + m[12] * m[6] * m[9];
+  let det = m[0] * t[0] + m[1] * t[4] + m[2] * t[8] + m[3] * t[12];
+  if det == 0.0 || !det.is_finite() {
+    return None
+  }
+  t[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15]
+    - m[9] * m[3] * m[14] - m[13] * m[2] * m[11] + m[13] * m[3] * m[10];
+  t[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15]
+    + m[8] * m[3] * m[14] + m[12] * m[2] * m[11] - m[12] * m[3] * m[10];
+  t[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15]
+    - m[8] * m[3] * m[13] - m[12] * m[1] * m[11] + m[12] * m[3] * m[9];
+  t[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14]
+    + m[8] * m[2] * m[13] + m[12] * m[1] * m[10] - m[12] * m[2] * m[9];
+  t[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15]
+    + m[5] * m[3] * m[14] + m[13] * m[2] * m[7] - m[13] * m[3] * m[6];
+  t[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15]
+    - m[4] * m[3] * m[14] - m[12] * m[2] * m[7] + m[12] * m[3] * m[6];
+  t[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15]
+    + m[4] * m[3] * m[13] + m[12] * m[1] * m[7] - m[12] * m[3] * m[5];
+  t[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14]
+    - m[4] * m[2] * m[13] - m[12] * m[1] * m[6] + m[12] * m[2] * m[5];
+  t[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11]
+    - m[5] * m[3] * m[10] - m[9] * m[2] * m[7] + m[9] * m[3] * m[6];
+  t[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11]
+    + m[4] * m[3] * m[10] + m[8] * m[2] * m[7] - m[8] * m[3] * m[6];
+  t[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11]
+    - m[4] * m[3] * m[9] - m[8] * m[1] * m[7] + m[8] * m[3] * m[5];
+  t[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10]
+    + m[4] * m[2] * m[9] + m[8] * m[1] * m[6] - m[8] * m[2] * m[5];
+  let r = 1.0 / det;
+  for item in t.iter_mut() {
+    *item *= r;
+  }
+  Some(t)
+}
+
+// 将屏幕上的点映射回节点z=0平面的本地坐标，3d时不能直接用逆矩阵乘点，
+// 而是取m对x/y/w有效的3x3部分求逆，相当于求视线和平面的交点
+pub fn inverse_point(x: f64, y: f64, m: &[f64; 16]) -> Option<(f64, f64)> {
+  if is_2d(m) {
+    return inverse(m).map(|t| (t[0] * x + t[4] * y + t[12], t[1] * x + t[5] * y + t[13]))
+  }
+  let a = m[0];
+  let b = m[4];
+  let c = m[12];
+  let d = m[1];
+  let e = m[5];
+  let f = m[13];
+  let g = m[3];
+  let h = m[7];
+  let i = m[15];
+  let ei = e * i - f * h;
+  let fg = f * g - d * i;
+  let dh = d * h - e * g;
+  let det = a * ei + b * fg + c * dh;
+  if det == 0.0 || !det.is_finite() {
+    return None
+  }
+  let u = ei * x + (c * h - b * i) * y + (b * f - c * e);
+  let v = fg * x + (a * i - c * g) * y + (c * d - a * f);
+  let w = dh * x + (b * g - a * h) * y + (a * e - b * d);
+  if w == 0.0 {
+    return None
+  }
+  let u = u / w;
+  let v = v / w;
+  // 交点在视点背后不可见
+  if g * u + h * v + i <= 0.0 {
+    return None
+  }
+  Some((u, v))
+}
+
 pub fn d2r(d: f64) -> f64 {
   d * PI / 180.0
 }
 
-// pub fn cal_rect_point(xa: f64, ya: f64, xb: f64, yb: f64, m: &[f64; 16])
-//   -> (f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64, f64) {
-//   let (x1, y1, z1, w1) = cal_point(xa, ya, 0.0, 1.0, m);
-//   let (x3, y3, z3, w3) = cal_point(xb, yb, 0.0, 1.0, m);
-//   let mut x2 = 0.0;
-//   let mut y2 = 0.0;
-//   let mut z2 = 0.0;
-//   let mut w2 = 1.0;
-//   let mut x4 = 0.0;
-//   let mut y4 = 0.0;
-//   let mut z4 = 0.0;
-//   let mut w4 = 1.0;
-//   // 无旋转的时候可以少算2个点
-//   if w1 == 1.0 && w3 == 1.0
-//     && m[1] == 0.0 && m[2] == 0.0 && m[4] == 0.0 && m[6] == 0.0 && m[7] == 0.0 && m[8] == 0.0 {
-//     x2 = x3;
-//     y2 = y1;
-//     z2 = z3;
-//     x4 = x1;
-//     y4 = y3;
-//     z2 = z1;
-//     z4 = z1;
-//   } else {
-//     (x2, y2, z2, w2) = cal_point(xb, ya, 0.0, 1.0, m);
-//     (x4, y4, z4, w4) = cal_point(xa, yb, 0.0, 1.0, m);
-//   }
-//   (x1, y1, z1, w1, x2, y2, z2, w2, x3, y3, z3, w3, x4, y4, z4, w4)
-// }
-
-// pub fn cal_point(x: f64, y: f64, z: f64, w: f64, m: &[f64; 16]) -> (f64, f64, f64, f64) {
-//   if !is_e(m) {
-//     let a1 = m[0];
-//     let b1 = m[1];
-//     let c1 = m[2];
-//     let d1 = m[3];
-//     let a2 = m[4];
-//     let b2 = m[5];
-//     let c2 = m[6];
-//     let d2 = m[7];
-//     let a3 = m[8];
-//     let b3 = m[9];
-//     let c3 = m[10];
-//     let d3 = m[11];
-//     let a4 = m[12];
-//     let b4 = m[13];
-//     let c4 = m[14];
-//     let d4 = m[15];
-//     let mut x0 = if a1 == 1.0 { x } else { x * a1 };
-//     if a2 != 0.0 {
-//       x0 += y * a2;
-//     }
-//     x0 += if w == 1.0 { a4 } else { a4 * w };
-//     let mut y0 = if b1 == 1.0 { x } else { x * b1 };
-//     if b2 != 0.0 {
-//       y0 += y * b2;
-//     }
-//     y0 += if w == 1.0 { b4 } else { b4 * w };
-//     let mut z0 = 0_f64;
-//     let mut w0 = w;
-//     if d1 != 0.0 || d2 != 0.0 || d3 != 0.0 {
-//       w0 = x * d1 + y * d2 + z * d3 + d4 * w;
-//     } else if d4 != 1.0 {
-//       w0 *= d4;
-//     }
-//     if z != 0.0 {
-//       x0 += z * a3;
-//       y0 += z * b3;
-//       z0 = x * c1 + y * c2 + c4 + z * c3;
-//     } else if c1 != 0.0 || c2 != 0.0 || c4 != 0.0 {
-//       z0 = x * c1 + y * c2 + c4;
-//     }
-//     return (x0, y0, z0, w0)
-//   }
-//   (x, y, z, w)
-// }
+// 矩形4个顶点变换后的坐标，顺序为左上、右上、右下、左下，每个点xyzw，和vt的存储一致
+pub fn cal_rect_point(xa: f64, ya: f64, xb: f64, yb: f64, m: &[f64; 16]) -> [f64; 16] {
+  let (x1, y1, z1, w1) = cal_point(xa, ya, 0.0, 1.0, m);
+  let (x3, y3, z3, w3) = cal_point(xb, yb, 0.0, 1.0, m);
+  // 无旋转的时候可以少算2个点
+  if w1 == 1.0 && w3 == 1.0
+    && m[1] == 0.0 && m[2] == 0.0 && m[3] == 0.0 && m[4] == 0.0 && m[6] == 0.0 && m[7] == 0.0 {
+    return [x1, y1, z1, w1, x3, y1, z1, w1, x3, y3, z3, w3, x1, y3, z1, w1]
+  }
+  let (x2, y2, z2, w2) = cal_point(xb, ya, 0.0, 1.0, m);
+  let (x4, y4, z4, w4) = cal_point(xa, yb, 0.0, 1.0, m);
+  [x1, y1, z1, w1, x2, y2, z2, w2, x3, y3, z3, w3, x4, y4, z4, w4]
+}
+
+// 矩形变换后在屏幕上的轴对齐包围盒[xa, ya, xb, yb]，有透视时需除以w，
+// 且w<=0的部分在视点背后，需先按w=W_MIN平面裁剪掉再投影，全部在背后时返回空盒子
+pub fn cal_bbox(p: &[f64; 16]) -> [f64; 4] {
+  if p[3] == 1.0 && p[7] == 1.0 && p[11] == 1.0 && p[15] == 1.0 {
+    return [
+      f64::min(f64::min(p[0], p[4]), f64::min(p[8], p[12])),
+      f64::min(f64::min(p[1], p[5]), f64::min(p[9], p[13])),
+      f64::max(f64::max(p[0], p[4]), f64::max(p[8], p[12])),
+      f64::max(f64::max(p[1], p[5]), f64::max(p[9], p[13])),
+    ]
+  }
+  let mut xa = f64::INFINITY;
+  let mut ya = f64::INFINITY;
+  let mut xb = f64::NEG_INFINITY;
+  let mut yb = f64::NEG_INFINITY;
+  let mut has = false;
+  let mut i = 0;
+  while i < 16 {
+    let j = (i + 4) % 16;
+    let (x1, y1, w1) = (p[i], p[i + 1], p[i + 3]);
+    let (x2, y2, w2) = (p[j], p[j + 1], p[j + 3]);
+    // 每条边取起点（可见时）和与裁剪面的交点（跨越时）
+    if w1 > W_MIN {
+      let (x, y) = (x1 / w1, y1 / w1);
+      xa = f64::min(xa, x);
+      ya = f64::min(ya, y);
+      xb = f64::max(xb, x);
+      yb = f64::max(yb, y);
+      has = true;
+    }
+    if (w1 > W_MIN) != (w2 > W_MIN) {
+      let t = (W_MIN - w1) / (w2 - w1);
+      let x = (x1 + (x2 - x1) * t) / W_MIN;
+      let y = (y1 + (y2 - y1) * t) / W_MIN;
+      xa = f64::min(xa, x);
+      ya = f64::min(ya, y);
+      xb = f64::max(xb, x);
+      yb = f64::max(yb, y);
+    }
+    i += 4;
+  }
+  if !has {
+    return [0.0, 0.0, 0.0, 0.0]
+  }
+  [xa, ya, xb, yb]
+}
+
+pub fn cal_point(x: f64, y: f64, z: f64, w: f64, m: &[f64; 16]) -> (f64, f64, f64, f64) {
+  if !is_e(m) {
+    let a1 = m[0];
+    let b1 = m[1];
+    let c1 = m[2];
+    let d1 = m[3];
+    let a2 = m[4];
+    let b2 = m[5];
+    let c2 = m[6];
+    let d2 = m[7];
+    let a3 = m[8];
+    let b3 = m[9];
+    let c3 = m[10];
+    let d3 = m[11];
+    let a4 = m[12];
+    let b4 = m[13];
+    let c4 = m[14];
+    let d4 = m[15];
+    let mut x0 = if a1 == 1.0 { x } else { x * a1 };
+    if a2 != 0.0 {
+      x0 += y * a2;
+    }
+    x0 += if w == 1.0 { a4 } else { a4 * w };
+    let mut y0 = if b2 == 1.0 { y } else { y * b2 };
+    if b1 != 0.0 {
+      y0 += x * b1;
+    }
+    y0 += if w == 1.0 { b4 } else { b4 * w };
+    let mut z0 = 0_f64;
+    let mut w0 = w;
+    if d1 != 0.0 || d2 != 0.0 || d3 != 0.0 {
+      w0 = x * d1 + y * d2 + z * d3 + d4 * w;
+    } else if d4 != 1.0 {
+      w0 *= d4;
+    }
+    if z != 0.0 {
+      x0 += z * a3;
+      y0 += z * b3;
+      z0 = x * c1 + y * c2 + c4 * w + z * c3;
+    } else if c1 != 0.0 || c2 != 0.0 || c4 != 0.0 {
+      z0 = x * c1 + y * c2 + c4 * w;
+    }
+    return (x0, y0, z0, w0)
+  }
+  (x, y, z, w)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+  }
+
+  // rotateY后再透视，z=0平面上的点w随x变化
+  fn perspective(deg: f64, d: f64) -> [f64; 16] {
+    let mut p = identity();
+    p[11] = -1.0 / d;
+    let mut r = identity();
+    multiply_rotate_y(&mut r, deg);
+    let mut m = identity();
+    multiply2(&p, &r, &mut m);
+    m
+  }
+
+  #[test]
+  fn inverse_2d() {
+    let mut m = identity();
+    multiply_scale_x(&mut m, 2.0);
+    multiply_rotate_z(&mut m, 30.0);
+    m[12] = 10.0;
+    m[13] = -5.0;
+    let t = inverse(&m).unwrap();
+    let mut e = identity();
+    multiply2(&m, &t, &mut e);
+    assert!(e.iter().zip(identity().iter()).all(|(a, b)| close(*a, *b)));
+    let (x, y, _, _) = cal_point(3.0, 4.0, 0.0, 1.0, &m);
+    let (u, v) = inverse_point(x, y, &m).unwrap();
+    assert!(close(u, 3.0) && close(v, 4.0));
+    multiply_scale_y(&mut m, 0.0);
+    assert!(inverse(&m).is_none());
+    assert!(inverse_point(1.0, 1.0, &m).is_none());
+  }
+
+  #[test]
+  fn inverse_3d() {
+    let m = perspective(30.0, 500.0);
+    let t = inverse(&m).unwrap();
+    let mut e = identity();
+    multiply2(&m, &t, &mut e);
+    assert!(e.iter().zip(identity().iter()).all(|(a, b)| close(*a, *b)));
+    // 投影到屏幕后再映射回z=0平面
+    let (x, y, _, w) = cal_point(40.0, 25.0, 0.0, 1.0, &m);
+    let (u, v) = inverse_point(x / w, y / w, &m).unwrap();
+    assert!(close(u, 40.0) && close(v, 25.0));
+  }
+
+  #[test]
+  fn bbox_2d() {
+    let m = identity();
+    assert_eq!(cal_bbox(&cal_rect_point(10.0, 20.0, 30.0, 60.0, &m)), [10.0, 20.0, 30.0, 60.0]);
+    let mut m = identity();
+    multiply_rotate_z(&mut m, 45.0);
+    let b = cal_bbox(&cal_rect_point(0.0, 0.0, 10.0, 10.0, &m));
+    let h = 10.0 * (0.5_f64).sqrt();
+    assert!(close(b[0], -h) && close(b[1], 0.0) && close(b[2], h) && close(b[3], 2.0 * h));
+  }
+
+  #[test]
+  fn bbox_perspective() {
+    // 全部在视点前方，等于4个顶点投影后的范围
+    let m = perspective(30.0, 500.0);
+    let p = cal_rect_point(0.0, 0.0, 100.0, 50.0, &m);
+    let b = cal_bbox(&p);
+    let xs = [p[0] / p[3], p[4] / p[7], p[8] / p[11], p[12] / p[15]];
+    assert!(close(b[0], xs.iter().cloned().fold(f64::INFINITY, f64::min)));
+    assert!(close(b[2], xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max)));
+    // 部分在视点背后时裁剪，结果依旧有限
+    let m = perspective(-60.0, 100.0);
+    let p = cal_rect_point(0.0, 0.0, 1000.0, 50.0, &m);
+    assert!(p[3] > 0.0 && p[7] <= 0.0);
+    let b = cal_bbox(&p);
+    assert!(b.iter().all(|x| x.is_finite()));
+    assert!(close(b[0], 0.0) && b[2] > 1000.0);
+    // 全部在视点背后
+    let p = cal_rect_point(200.0, 0.0, 1000.0, 50.0, &m);
+    assert_eq!(cal_bbox(&p), [0.0, 0.0, 0.0, 0.0]);
+  }
+}
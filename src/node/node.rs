@@ -2,15 +2,28 @@ use std::ptr;
 use wasm_bindgen::prelude::*;
 use crate::{wasm_ptr};
 use crate::node::Root;
-use crate::style::style_unit;
+use crate::style::{style_unit, CalcItem, to_calc};
 use crate::style::style_key::*;
 use crate::refresh::refresh_level;
-use crate::animation::{Animation, RUNNING};
+use crate::animation::{Animation, Transition, RUNNING, NORMAL, FORWARDS, EASE_CUSTOM, REPLACE, ADD, ACCUMULATE};
 use crate::math::*;
 
+// 未添加到root时font-size的基准，同浏览器默认
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+// css的transition，对应key的值变化时自动生成动画
+struct TransitionSpec {
+  k: usize,
+  duration: f64,
+  delay: f64,
+  easing: u8,
+  bezier: [f64; 4],
+}
+
 #[wasm_bindgen]
 pub struct Node {
   pub root: *mut Root,
+  pub(crate) parent: *mut Node, // 由root在增删节点时维护，root节点为空
   pub is_text: bool,
   pub x: f64,
   pub y: f64,
@@ -21,22 +34,36 @@ pub struct Node {
   pub xb: f64,
   pub yb: f64,
   pub lv: usize,
+  pub total: usize, // 先序列表中子孙节点的数量，由root在增删节点时维护
   pub refresh_level: usize,
-  current_style: [f64; 18],
-  current_unit: [usize; 18],
-  computed_style: [f64; 18],
+  current_style: [f64; LENGTH],
+  current_unit: [usize; LENGTH],
+  computed_style: [f64; LENGTH],
+  current_calc: [Vec<CalcItem>; LENGTH], // 单位为calc时的各项
+  current_color: [[f64; 4]; COLOR_LENGTH], // 颜色样式的rgba，下标为k - COLOR
+  base_style: [f64; LENGTH], // 有非replace动画时，被叠加的基础样式，仅composite_keys中的有效
+  base_unit: [usize; LENGTH],
+  base_color: [[f64; 4]; COLOR_LENGTH],
+  composite_keys: Vec<usize>,
   transform: [f64; 16],
   matrix: [f64; 16],
-  matrix_event: [f64; 16],
+  pub(crate) matrix_event: [f64; 16],
+  perspective_matrix: [f64; 16], // 自身perspective作用于子节点的矩阵
   pub opacity: f64, // 存储包含父继承的最终世界opacity
+  pub font_size: f64, // 计算后的font-size，em单位以此为基准
+  font_size_style: f64,
+  font_size_unit: usize,
   animations: Vec<*mut Animation>,
+  transition_specs: Vec<TransitionSpec>,
+  implicit: Vec<Animation>, // transition生成的动画，由node自身持有
 }
 
 #[wasm_bindgen]
 impl Node {
   pub fn new(is_text: bool) -> Node {
-    Node {
+    let mut node = Node {
       root: ptr::null_mut(),
+      parent: ptr::null_mut(),
       is_text,
       x: 0.0,
       y: 0.0,
@@ -47,29 +74,119 @@ impl Node {
       xb: 0.0,
       yb: 0.0,
       lv: 0,
+      total: 0,
       refresh_level: refresh_level::NONE,
-      current_style: [0.0; 18],
-      current_unit: [0; 18],
-      computed_style: [0.0; 18],
+      current_style: [0.0; LENGTH],
+      current_unit: [0; LENGTH],
+      computed_style: [0.0; LENGTH],
+      current_calc: Default::default(),
+      current_color: [[0.0; 4]; COLOR_LENGTH],
+      base_style: [0.0; LENGTH],
+      base_unit: [0; LENGTH],
+      base_color: [[0.0; 4]; COLOR_LENGTH],
+      composite_keys: Vec::new(),
       transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
       matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
       matrix_event: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+      perspective_matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
       opacity: 1.0,
+      font_size: 0.0,
+      font_size_style: 0.0,
+      font_size_unit: style_unit::INHERIT,
       animations: Vec::new(),
-    }
+      transition_specs: Vec::new(),
+      implicit: Vec::new(),
+    };
+    // opacity默认为1，set_style之前读取也不会被视为不可见
+    node.current_style[OPACITY] = 1.0;
+    node.computed_style[OPACITY] = 1.0;
+    node.base_style[OPACITY] = 1.0;
+    node
   }
 
   pub fn set_root(&mut self, root: *mut Root) -> () {
     self.root = root;
   }
 
+  // font-size默认继承，em和百分比相对父节点，变化后子孙节点在root.refresh()时更新
+  pub fn set_font_size(&mut self, v: f64, u: usize) -> () {
+    self.font_size_style = v;
+    self.font_size_unit = u;
+    let old = self.font_size;
+    self.cal_font_size();
+    if self.font_size != old {
+      self.recal_unit(|u| u == style_unit::EM);
+      self.refresh_level |= refresh_level::REFLOW;
+    }
+  }
+
+  pub fn cal_font_size(&mut self) -> () {
+    let p = if !self.parent.is_null() {
+      unsafe { (*self.parent).font_size }
+    } else if !self.root.is_null() {
+      unsafe { (*self.root).font_size }
+    } else {
+      DEFAULT_FONT_SIZE
+    };
+    let v = self.font_size_style;
+    let u = self.font_size_unit;
+    // 未添加到root时视口尺寸为0，rem使用默认值
+    let (w, h, rem) = if self.root.is_null() {
+      (0.0, 0.0, DEFAULT_FONT_SIZE)
+    } else {
+      let root = unsafe { &*self.root };
+      (root.width, root.height, root.font_size)
+    };
+    // font-size的百分比和em均相对父节点的font-size，不支持calc，同inherit
+    self.font_size = match u {
+      style_unit::PX | style_unit::NUMBER => v,
+      style_unit::PERCENT => v * p * 0.01,
+      style_unit::EM => v * p,
+      style_unit::REM => v * rem,
+      style_unit::VW => v * w * 0.01,
+      style_unit::VH => v * h * 0.01,
+      style_unit::VMAX => v * f64::max(w, h) * 0.01,
+      style_unit::VMIN => v * f64::min(w, h) * 0.01,
+      _ => p,
+    };
+  }
+
+  // 使用了满足条件单位的样式需重新计算，如font-size或root尺寸变化时
+  pub(crate) fn recal_unit(&mut self, f: fn(usize) -> bool) -> () {
+    let mut rl = refresh_level::NONE;
+    if self.has_unit(TRANSLATE_X, f) {
+      rl |= refresh_level::TRANSLATE_X;
+    }
+    if self.has_unit(TRANSLATE_Y, f) {
+      rl |= refresh_level::TRANSLATE_Y;
+    }
+    if self.has_unit(TRANSLATE_Z, f) {
+      rl |= refresh_level::TRANSLATE_Z;
+    }
+    if self.has_unit(TFO_X, f) || self.has_unit(TFO_Y, f) || self.has_unit(TFO_Z, f) {
+      rl |= refresh_level::TRANSFORM;
+    }
+    if rl != refresh_level::NONE {
+      self.cal_matrix(rl);
+    }
+    if self.has_unit(PERSPECTIVE, f)
+      || self.has_unit(PERSPECTIVE_ORIGIN_X, f)
+      || self.has_unit(PERSPECTIVE_ORIGIN_Y, f) {
+      self.cal_perspective();
+    }
+  }
+
   pub fn add_ani(&mut self, animation: *mut Animation) -> () {
     let animation = wasm_ptr::transform_mut(animation);
     self.animations.push(animation);
   }
 
   pub fn remove_ani(&mut self, animation: *mut Animation) -> () {
+    let animation = wasm_ptr::transform_mut(animation);
     self.animations.retain(|&x| x != animation);
+    if !self.composite_keys.is_empty() {
+      self.cal_composite();
+    }
   }
 
   pub fn clear(&mut self) -> () {
@@ -81,6 +198,81 @@ impl Node {
       count += 1;
     }
     self.animations.clear();
+    self.implicit.clear();
+    self.cal_composite();
+  }
+
+  // easing同Animation，EASE_CUSTOM时再调用set_transition_bezier，duration为0则删除
+  pub fn set_transition(&mut self, k: usize, duration: f64, delay: f64, easing: u8) -> () {
+    self.transition_specs.retain(|x| x.k != k);
+    if duration > 0.0 {
+      self.transition_specs.push(TransitionSpec {
+        k,
+        duration,
+        delay,
+        easing,
+        bezier: [0.0, 0.0, 1.0, 1.0],
+      });
+    }
+  }
+
+  pub fn set_transition_bezier(&mut self, k: usize, x1: f64, y1: f64, x2: f64, y2: f64) -> () {
+    if let Some(x) = self.transition_specs.iter_mut().find(|x| x.k == k) {
+      x.bezier = [x1, y1, x2, y2];
+    }
+  }
+
+  pub fn clear_transition(&mut self) -> () {
+    self.transition_specs.clear();
+  }
+
+  // 有transition设置时生成从当前值到v的动画，正在进行的同key动画被打断并继承其速度，返回是否生成
+  fn start_transition(&mut self, k: usize, v: f64, u: usize, c: &[f64; 4]) -> bool {
+    let (duration, delay, easing, bezier) = match self.transition_specs.iter().find(|x| x.k == k) {
+      Some(x) => (x.duration, x.delay, x.easing, x.bezier),
+      None => return false,
+    };
+    // 目标值未变的继续运行
+    if self.implicit.iter().any(|x| x.has_key(k) && x.is_target(k, v, u, c)) {
+      return true
+    }
+    let is_color = k >= COLOR;
+    // 单位不同或calc无法直接插值，同css立即生效
+    if !is_color && (u != self.current_unit[k] || u == style_unit::CALC) {
+      return false
+    }
+    let node: *mut Node = self;
+    let mut ani = Animation::from_node(node, NORMAL, duration, 0, delay, 0.0, FORWARDS,
+      1.0, 1, 0.0, 0.0, easing);
+    if easing == EASE_CUSTOM {
+      ani.set_bezier(bezier[0], bezier[1], bezier[2], bezier[3]);
+    }
+    ani.implicit = true;
+    let mut velocity = 0.0;
+    if let Some(i) = self.implicit.iter().position(|x| x.has_key(k)) {
+      velocity = self.implicit[i].get_velocity(k);
+      self.implicit.remove(i);
+    }
+    ani.add_frame(false, 0.0, 0, 0.0, 0.0, 1.0, 1.0);
+    if is_color {
+      let s = &self.current_color[k - COLOR];
+      ani.add_item_color(false, k, s[0], s[1], s[2], s[3],
+        c[0] - s[0], c[1] - s[1], c[2] - s[2], c[3] - s[3]);
+    } else {
+      ani.add_item(false, k, self.current_style[k], u, v - self.current_style[k]);
+    }
+    ani.add_frame(false, duration, 0, 0.0, 0.0, 1.0, 1.0);
+    if is_color {
+      ani.add_item_color(false, k, c[0], c[1], c[2], c[3], 0.0, 0.0, 0.0, 0.0);
+    } else {
+      ani.add_item(false, k, v, u, 0.0);
+    }
+    if velocity != 0.0 {
+      ani.set_velocity(k, velocity);
+    }
+    ani.play();
+    self.implicit.push(ani);
+    true
   }
 
   pub fn set_style(&mut self, x: f64, y: f64, offset_width: f64, offset_height: f64,
@@ -120,9 +312,9 @@ impl Node {
     self.current_unit[2] = cu2;
     self.current_unit[16] = cu16;
     self.current_unit[17] = cu17;
-    self.computed_style[0] = self.cal_size(cs0, cu0, offset_width);
-    self.computed_style[1] = self.cal_size(cs1, cu1, offset_height);
-    self.computed_style[2] = self.cal_size(cs2, cu2, offset_width);
+    self.computed_style[0] = self.cal_size(0, cs0, cu0, offset_width);
+    self.computed_style[1] = self.cal_size(1, cs1, cu1, offset_height);
+    self.computed_style[2] = self.cal_size(2, cs2, cu2, offset_width);
     self.computed_style[3] = cs3;
     self.computed_style[4] = cs4;
     self.computed_style[5] = cs5;
@@ -136,9 +328,10 @@ impl Node {
     self.computed_style[13] = cs13;
     self.computed_style[14] = cs14;
     self.computed_style[15] = cs15;
-    self.computed_style[16] = self.cal_size(cs16, cu16, offset_width);
-    self.computed_style[17] = self.cal_size(cs17, cu17, offset_height);
+    self.computed_style[16] = self.cal_size(16, cs16, cu16, offset_width);
+    self.computed_style[17] = self.cal_size(17, cs17, cu17, offset_height);
     self.cal_matrix(refresh_level::REFLOW);
+    self.cal_perspective();
   }
 
   pub fn set_xywh(&mut self, x: f64, y: f64, offset_width: f64, offset_height: f64) -> () {
@@ -152,6 +345,7 @@ impl Node {
     self.xb = x + offset_width.ceil();
     self.yb = y + offset_height.ceil();
     self.cal_matrix(refresh_level::REFLOW);
+    self.cal_perspective();
   }
 
   // 文本style复用parent，所以只需要设置尺寸位置
@@ -164,6 +358,7 @@ impl Node {
     self.ya = y;
     self.xb = x + offset_width.ceil();
     self.yb = y + offset_height.ceil();
+    self.refresh_level |= refresh_level::REFLOW;
   }
 
   pub fn set_transform(&mut self, a: f64, b: f64, c: f64, d: f64,
@@ -204,8 +399,23 @@ impl Node {
     self.matrix_event.as_ptr()
   }
 
+  pub fn pm_ptr(&self) -> *const f64 {
+    self.perspective_matrix.as_ptr()
+  }
+
+  // 按COLOR顺序每4个一组的rgba
+  pub fn color_ptr(&self) -> *const f64 {
+    self.current_color.as_ptr() as *const f64
+  }
+
+  // root坐标系的点映射到节点本地坐标系，matrix_event不可逆时返回undefined
+  pub fn to_local(&self, x: f64, y: f64) -> Option<Vec<f64>> {
+    inverse_point(x, y, &self.matrix_event).map(|(x, y)| vec![x, y])
+  }
+
+  // 自身的opacity，不含父继承
   pub fn get_op(&self) -> f64 {
-    self.opacity
+    self.computed_style[OPACITY]
   }
 
   pub fn get_rl(&self) -> usize {
@@ -216,18 +426,40 @@ impl Node {
     let mut count = 0;
     let len = self.animations.len();
     let mut res = 0;
-    self.refresh_level = refresh_level::NONE;
+    // refresh_level在root.refresh()消费后重置，这里只累加
+    // transition先执行，同css被显式的动画覆盖
+    while count < self.implicit.len() {
+      let ani: *mut Animation = &mut self.implicit[count];
+      let ani = unsafe { &mut *ani };
+      if ani.play_state == RUNNING && ani.before(diff) {
+        res += 1;
+        self.cal_trans(ani);
+      }
+      count += 1;
+    }
+    count = 0;
+    let mut composite = false;
     while count < len {
       let ani = unsafe { &mut *self.animations[count] };
-      if ani.play_state == RUNNING {
+      if ani.play_state == RUNNING && !ani.in_group {
         // 需要刷新的动画返回计数+1
         if ani.before(diff) {
           res += 1;
-          self.cal_trans(ani);
+          if ani.composite == REPLACE {
+            self.cal_trans(ani);
+          } else {
+            composite = true;
+          }
+        } else if ani.composite != REPLACE && ani.finished {
+          // 无fill结束时需移除叠加的值
+          composite = true;
         }
       }
       count += 1;
     }
+    if composite {
+      self.cal_composite();
+    }
     res
   }
 
@@ -237,12 +469,14 @@ impl Node {
     let mut res = 0;
     while count < len {
       let ani = unsafe { &mut *self.animations[count] };
-      if ani.play_state == RUNNING {
+      if ani.play_state == RUNNING && !ani.in_group {
         ani.after();
         res += 1;
       }
       count += 1;
     }
+    // transition结束后直接移除，不计入js侧的数量
+    self.implicit.retain_mut(|ani| !ani.after());
     res
   }
 
@@ -275,16 +509,50 @@ impl Node {
       self.refresh_level |= refresh_level::TRANSFORM;
     } else if k == TFO_Y {
       self.refresh_level |= refresh_level::TRANSFORM;
+    } else if k == TFO_Z {
+      self.refresh_level |= refresh_level::TRANSFORM;
+    } else if k == PERSPECTIVE || k == PERSPECTIVE_ORIGIN_X || k == PERSPECTIVE_ORIGIN_Y {
+      self.refresh_level |= refresh_level::PERSPECTIVE;
+    } else if k >= COLOR {
+      self.refresh_level |= refresh_level::REPAINT;
     }
   }
 
   pub fn cal_trans(&mut self, ani: &mut Animation) {
+    if ani.composite != REPLACE {
+      self.cal_composite();
+      return
+    }
     let ts = ani.get_transition();
+    let mut composite = false;
     for item in ts.iter() {
+      // 被叠加的key写入基础样式，再重新合成
+      if self.composite_keys.contains(&item.k) {
+        self.set_base(item.k, item.v, item.u, &item.c);
+        composite = true;
+        continue;
+      }
+      if item.u == style_unit::RGBA {
+        self.current_color[item.k - COLOR] = item.c;
+        self.set_refresh_level(item.k);
+        continue;
+      }
       self.current_style[item.k] = item.v;
       self.current_unit[item.k] = item.u;
+      if item.u == style_unit::CALC {
+        self.current_calc[item.k] = item.calc.clone();
+      }
       self.set_refresh_level(item.k);
     }
+    if composite {
+      self.cal_composite();
+      return
+    }
+    self.cal_trans_style();
+  }
+
+  // 样式变化后更新矩阵等计算值
+  fn cal_trans_style(&mut self) -> () {
     if self.refresh_level & refresh_level::TRANSFORM_ALL > 0 {
       self.cal_matrix(self.refresh_level);
     }
@@ -292,9 +560,144 @@ impl Node {
       self.computed_style[OPACITY]
         = self.current_style[OPACITY];
     }
+    if self.refresh_level & refresh_level::PERSPECTIVE > 0 {
+      self.cal_perspective();
+    }
+  }
+
+  fn set_base(&mut self, k: usize, v: f64, u: usize, c: &[f64; 4]) -> () {
+    if k >= COLOR {
+      self.base_color[k - COLOR] = *c;
+    } else {
+      self.base_style[k] = v;
+      self.base_unit[k] = u;
+    }
+  }
+
+  // 非replace的动画按顺序叠加在基础样式上，calc和单位不同的无法叠加，同replace
+  fn cal_composite(&mut self) -> () {
+    let mut keys: Vec<usize> = Vec::new();
+    let mut list: Vec<(u8, &Transition)> = Vec::new();
+    let animations: Vec<*mut Animation> = self.animations.clone();
+    for ani in animations.iter() {
+      let ani = unsafe { & **ani };
+      if ani.composite == REPLACE {
+        continue;
+      }
+      for item in ani.get_applied().iter() {
+        if !keys.contains(&item.k) {
+          keys.push(item.k);
+        }
+        list.push((ani.composite, item));
+      }
+    }
+    // 新叠加的key当前值作为基础样式，不再叠加的恢复
+    for &k in keys.iter() {
+      if !self.composite_keys.contains(&k) {
+        if k >= COLOR {
+          self.base_color[k - COLOR] = self.current_color[k - COLOR];
+        } else {
+          self.base_style[k] = self.current_style[k];
+          self.base_unit[k] = self.current_unit[k];
+        }
+      }
+    }
+    let old = std::mem::replace(&mut self.composite_keys, keys);
+    for &k in old.iter() {
+      if !self.composite_keys.contains(&k) {
+        if k >= COLOR {
+          self.set_color_value(k, self.base_color[k - COLOR]);
+        } else {
+          self.set_style_value(k, self.base_style[k], self.base_unit[k]);
+        }
+      }
+    }
+    for i in 0..self.composite_keys.len() {
+      let k = self.composite_keys[i];
+      if k >= COLOR {
+        let mut c = self.base_color[k - COLOR];
+        for (_, item) in list.iter().filter(|(_, item)| item.k == k) {
+          for (v, d) in c.iter_mut().zip(item.c.iter()) {
+            *v += d;
+          }
+        }
+        for v in c[..3].iter_mut() {
+          *v = f64::min(255.0, *v);
+        }
+        c[3] = f64::min(1.0, c[3]);
+        self.set_color_value(k, c);
+        continue;
+      }
+      let mut v = self.base_style[k];
+      let mut u = self.base_unit[k];
+      let mut calc = None;
+      for (composite, item) in list.iter().filter(|(_, item)| item.k == k) {
+        if item.u != u || u == style_unit::CALC {
+          v = item.v;
+          u = item.u;
+          calc = Some(&item.calc);
+        } else if k == SCALE_X || k == SCALE_Y || k == SCALE_Z {
+          // 同transform列表，add相乘，accumulate的scale以1为基准相加
+          if *composite == ADD {
+            v *= item.v;
+          } else if *composite == ACCUMULATE {
+            v += item.v - 1.0;
+          }
+        } else {
+          v += item.v;
+        }
+      }
+      if let (style_unit::CALC, Some(calc)) = (u, calc) {
+        if self.current_calc[k] != *calc {
+          self.current_calc[k] = calc.clone();
+          self.set_refresh_level(k);
+        }
+      }
+      self.set_style_value(k, v, u);
+    }
+    self.cal_trans_style();
+  }
+
+  // set_style之外的key，如perspective、perspective-origin和transform-origin的z，需在set_style前调用
+  pub fn set_style_value(&mut self, k: usize, v: f64, u: usize) -> () {
+    if self.current_style[k] != v || self.current_unit[k] != u {
+      self.current_style[k] = v;
+      self.current_unit[k] = u;
+      self.set_refresh_level(k);
+    }
+  }
+
+  fn set_color_value(&mut self, k: usize, c: [f64; 4]) -> () {
+    if self.current_color[k - COLOR] != c {
+      self.current_color[k - COLOR] = c;
+      self.set_refresh_level(k);
+    }
+  }
+
+  // 同css，perspective作用于子节点，以perspective-origin为原点，root.refresh()时预乘在子节点matrix之前
+  pub fn cal_perspective(&mut self) -> () {
+    self.refresh_level |= refresh_level::PERSPECTIVE;
+    let v = self.cal_size(PERSPECTIVE, self.current_style[PERSPECTIVE], self.current_unit[PERSPECTIVE], self.offset_width);
+    self.computed_style[PERSPECTIVE] = v;
+    let ox = self.cal_size(PERSPECTIVE_ORIGIN_X, self.current_style[PERSPECTIVE_ORIGIN_X], self.current_unit[PERSPECTIVE_ORIGIN_X], self.offset_width);
+    self.computed_style[PERSPECTIVE_ORIGIN_X] = ox;
+    let oy = self.cal_size(PERSPECTIVE_ORIGIN_Y, self.current_style[PERSPECTIVE_ORIGIN_Y], self.current_unit[PERSPECTIVE_ORIGIN_Y], self.offset_height);
+    self.computed_style[PERSPECTIVE_ORIGIN_Y] = oy;
+    let m = &mut self.perspective_matrix;
+    assign_m(m, &identity());
+    // 0或负数同none
+    if v <= 0.0 {
+      return
+    }
+    m[11] = -1.0 / v;
+    let ox = ox + self.x;
+    let oy = oy + self.y;
+    tfo_multiply(m, ox, oy, 0.0);
+    multiply_tfo(m, -ox, -oy, 0.0);
   }
 
   pub fn cal_matrix(&mut self, rl: usize) -> () {
+    self.refresh_level |= rl;
     let mut optimize = true;
     if rl & refresh_level::TRANSFORM > 0 || rl >= refresh_level::REPAINT {
       optimize = false;
@@ -313,21 +716,21 @@ impl Node {
     }
     if optimize {
       if rl & refresh_level::TRANSLATE_X > 0 {
-        let v = self.cal_size(self.current_style[TRANSLATE_X], self.current_unit[TRANSLATE_X], self.offset_width);
+        let v = self.cal_size(TRANSLATE_X, self.current_style[TRANSLATE_X], self.current_unit[TRANSLATE_X], self.offset_width);
         let x = v - self.computed_style[TRANSLATE_X];
         self.computed_style[TRANSLATE_X] = v;
         self.transform[12] += x;
         self.matrix[12] += x;
       }
       if rl & refresh_level::TRANSLATE_Y > 0 {
-        let v = self.cal_size(self.current_style[TRANSLATE_Y], self.current_unit[TRANSLATE_Y], self.offset_height);
+        let v = self.cal_size(TRANSLATE_Y, self.current_style[TRANSLATE_Y], self.current_unit[TRANSLATE_Y], self.offset_height);
         let y = v - self.computed_style[TRANSLATE_Y];
         self.computed_style[TRANSLATE_Y] = v;
         self.transform[13] += y;
         self.matrix[13] += y;
       }
       if rl & refresh_level::TRANSLATE_Z > 0 {
-        let v = self.cal_size(self.current_style[TRANSLATE_Z], self.current_unit[TRANSLATE_Z], self.offset_width);
+        let v = self.cal_size(TRANSLATE_Z, self.current_style[TRANSLATE_Z], self.current_unit[TRANSLATE_Z], self.offset_width);
         let z = v - self.computed_style[TRANSLATE_Z];
         self.computed_style[TRANSLATE_Z] = v;
         self.transform[14] += z;
@@ -355,8 +758,9 @@ impl Node {
         self.matrix[5] = cy;
         let ox = self.computed_style[TFO_X] + self.x;
         let oy = self.computed_style[TFO_Y] + self.y;
-        self.matrix[12] = self.transform[12] + ox - cx * ox - oy * sy;
-        self.matrix[13] = self.transform[13] + oy - sx * ox - oy * cy;
+        let oz = self.computed_style[TFO_Z];
+        self.matrix[12] = self.transform[12] + ox - cx * ox - oy * sy - self.transform[8] * oz;
+        self.matrix[13] = self.transform[13] + oy - sx * ox - oy * cy - self.transform[9] * oz;
       }
       if rl & refresh_level::SCALE > 0 {
         if rl & refresh_level::SCALE_X > 0 {
@@ -403,22 +807,23 @@ impl Node {
         }
         let ox = self.computed_style[TFO_X] + self.x;
         let oy = self.computed_style[TFO_Y] + self.y;
-        self.matrix[12] = self.transform[12] + ox - self.transform[0] * ox - self.transform[4] * oy;
-        self.matrix[13] = self.transform[13] + oy - self.transform[1] * ox - self.transform[5] * oy;
-        self.matrix[14] = self.transform[14] - self.transform[2] * ox - self.transform[6] * oy;
+        let oz = self.computed_style[TFO_Z];
+        self.matrix[12] = self.transform[12] + ox - self.transform[0] * ox - self.transform[4] * oy - self.transform[8] * oz;
+        self.matrix[13] = self.transform[13] + oy - self.transform[1] * ox - self.transform[5] * oy - self.transform[9] * oz;
+        self.matrix[14] = self.transform[14] + oz - self.transform[2] * ox - self.transform[6] * oy - self.transform[10] * oz;
       }
     } else {
       self.set_transform(1.0, 0.0, 0.0, 0.0,
                       0.0, 1.0, 0.0, 0.0,
                       0.0, 0.0, 1.0, 0.0,
                       0.0, 0.0, 0.0, 1.0);
-      let v = self.cal_size(self.current_style[TRANSLATE_X], self.current_unit[TRANSLATE_X], self.offset_width);
+      let v = self.cal_size(TRANSLATE_X, self.current_style[TRANSLATE_X], self.current_unit[TRANSLATE_X], self.offset_width);
       self.computed_style[TRANSLATE_X] = v;
       self.transform[12] = v;
-      let v = self.cal_size(self.current_style[TRANSLATE_Y], self.current_unit[TRANSLATE_Y], self.offset_height);
+      let v = self.cal_size(TRANSLATE_Y, self.current_style[TRANSLATE_Y], self.current_unit[TRANSLATE_Y], self.offset_height);
       self.computed_style[TRANSLATE_Y] = v;
       self.transform[13] = v;
-      let v = self.cal_size(self.current_style[TRANSLATE_Z], self.current_unit[TRANSLATE_Z], self.offset_width);
+      let v = self.cal_size(TRANSLATE_Z, self.current_style[TRANSLATE_Z], self.current_unit[TRANSLATE_Z], self.offset_width);
       self.computed_style[TRANSLATE_Z] = v;
       self.transform[14] = v;
       let v = self.current_style[ROTATE_X];
@@ -461,19 +866,42 @@ impl Node {
       self.computed_style[SCALE_Z] = v;
       multiply_scale_z(&mut self.transform, v);
       assign_m(&mut self.matrix, &self.transform);
+      // transform-origin也可能被动画改变，需重新计算
+      let v = self.cal_size(TFO_X, self.current_style[TFO_X], self.current_unit[TFO_X], self.offset_width);
+      self.computed_style[TFO_X] = v;
+      let v = self.cal_size(TFO_Y, self.current_style[TFO_Y], self.current_unit[TFO_Y], self.offset_height);
+      self.computed_style[TFO_Y] = v;
+      let v = self.cal_size(TFO_Z, self.current_style[TFO_Z], self.current_unit[TFO_Z], self.offset_width);
+      self.computed_style[TFO_Z] = v;
       let ox = self.computed_style[TFO_X] + self.x;
       let oy = self.computed_style[TFO_Y] + self.y;
-      if ox == 0.0 && oy == 0.0 || is_e(&self.matrix) {
+      let oz = self.computed_style[TFO_Z];
+      if ox == 0.0 && oy == 0.0 && oz == 0.0 || is_e(&self.matrix) {
         return
       }
-      tfo_multiply(&mut self.matrix, ox, oy);
-      multiply_tfo(&mut self.matrix, -ox, -oy);
+      tfo_multiply(&mut self.matrix, ox, oy, oz);
+      multiply_tfo(&mut self.matrix, -ox, -oy, -oz);
     }
   }
 
-  pub fn cal_size(&self, v: f64, u: usize, parent: f64) -> f64 {
-    if u == style_unit::PERCENT {
+  // k为样式key，inherit时取父节点对应的计算值
+  pub fn cal_size(&self, k: usize, v: f64, u: usize, parent: f64) -> f64 {
+    if u == style_unit::CALC {
+      let mut res = 0.0;
+      for item in self.current_calc[k].iter() {
+        res += self.cal_size(k, item.v, item.u, parent);
+      }
+      return res
+    } else if u == style_unit::PERCENT {
       return v * parent * 0.01
+    } else if u == style_unit::EM {
+      return v * self.font_size
+    } else if u == style_unit::INHERIT {
+      if self.parent.is_null() {
+        return 0.0
+      }
+      let p = unsafe { &*self.parent };
+      return p.computed_style[k]
     } else if u == style_unit::VW {
       let root = unsafe { &*self.root };
       return v * root.width * 0.01
@@ -498,8 +926,15 @@ impl Node {
   }
 
   pub fn equal_set_style(&mut self, k: usize, v: f64, u: usize) -> bool {
+    // 被叠加时修改的是基础样式
+    if self.composite_keys.contains(&k) {
+      let res = self.base_style[k] == v && self.base_unit[k] == u;
+      self.set_base(k, v, u, &[0.0; 4]);
+      self.cal_composite();
+      return res
+    }
     let res = self.current_style[k] == v && self.current_unit[k] == u;
-    if !res {
+    if !res && !self.start_transition(k, v, u, &[0.0; 4]) {
       self.current_style[k] = v;
       self.current_unit[k] = u;
       self.set_refresh_level(k);
@@ -508,29 +943,278 @@ impl Node {
   }
 
   pub fn update_style(&mut self, k: usize, v: f64, u: usize) -> () {
-    self.current_style[k] = v;
-    self.current_unit[k] = u;
+    // 同equal_set_style，被叠加时修改的是基础样式
+    if self.composite_keys.contains(&k) {
+      self.set_base(k, v, u, &[0.0; 4]);
+      self.cal_composite();
+      return
+    }
+    if self.equal_style(k, v, u) || !self.start_transition(k, v, u, &[0.0; 4]) {
+      self.current_style[k] = v;
+      self.current_unit[k] = u;
+    }
+  }
+
+  // calc()值用数值和单位两个数组传入，仅translate和transform-origin支持，需在set_style前调用
+  pub fn set_calc(&mut self, k: usize, v: Vec<f64>, u: Vec<usize>) -> () {
+    self.current_style[k] = 0.0;
+    self.current_unit[k] = style_unit::CALC;
+    self.current_calc[k] = to_calc(&v, &u);
+  }
+
+  pub fn equal_set_calc(&mut self, k: usize, v: Vec<f64>, u: Vec<usize>) -> bool {
+    let calc = to_calc(&v, &u);
+    let res = self.equal_calc(k, &calc);
+    if !res {
+      self.current_style[k] = 0.0;
+      self.current_unit[k] = style_unit::CALC;
+      self.current_calc[k] = calc;
+      self.set_refresh_level(k);
+    }
+    res
+  }
+
+  pub(crate) fn equal_calc(&self, k: usize, calc: &[CalcItem]) -> bool {
+    self.current_unit[k] == style_unit::CALC && self.current_calc[k] == calc
+  }
+
+  // 颜色值rgb范围0~255，a范围0~1
+  pub fn set_color(&mut self, k: usize, r: f64, g: f64, b: f64, a: f64) -> () {
+    self.current_color[k - COLOR] = [r, g, b, a];
+  }
+
+  pub fn equal_set_color(&mut self, k: usize, r: f64, g: f64, b: f64, a: f64) -> bool {
+    let c = [r, g, b, a];
+    if self.composite_keys.contains(&k) {
+      let res = self.base_color[k - COLOR] == c;
+      self.set_base(k, 0.0, style_unit::RGBA, &c);
+      self.cal_composite();
+      return res
+    }
+    let res = self.equal_color(k, &c);
+    if !res && !self.start_transition(k, 0.0, style_unit::RGBA, &c) {
+      self.current_color[k - COLOR] = c;
+      self.set_refresh_level(k);
+    }
+    res
+  }
+
+  pub(crate) fn equal_color(&self, k: usize, c: &[f64; 4]) -> bool {
+    self.current_color[k - COLOR] == *c
+  }
+
+  // 样式的单位是否满足条件，calc时看其中任意一项
+  fn has_unit(&self, k: usize, f: fn(usize) -> bool) -> bool {
+    let u = self.current_unit[k];
+    if u == style_unit::CALC {
+      return self.current_calc[k].iter().any(|item| f(item.u))
+    }
+    f(u)
   }
 
   pub fn offset_x(&mut self, v: f64) -> () {
     self.x += v;
     self.xa += v;
     self.xb = self.xa + self.offset_width.ceil();
+    self.refresh_level |= refresh_level::REFLOW;
   }
 
   pub fn offset_y(&mut self, v: f64) -> () {
     self.y += v;
     self.ya += v;
     self.yb = self.ya + self.offset_height.ceil();
+    self.refresh_level |= refresh_level::REFLOW;
   }
 
   pub fn resize_x(&mut self, v: f64) -> () {
     self.offset_width += v;
     self.xb = self.xa + self.offset_width.ceil();
+    self.refresh_level |= refresh_level::REFLOW;
   }
 
   pub fn resize_y(&mut self, v: f64) -> () {
     self.offset_height += v;
     self.yb = self.ya + self.offset_height.ceil();
+    self.refresh_level |= refresh_level::REFLOW;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::animation::LINEAR;
+  use crate::test_util::{get, add_node, add_ani, translate_x, tick};
+
+  fn create() -> Node {
+    let mut node = Node::new(false);
+    node.current_style[TRANSLATE_X] = 0.0;
+    node.current_unit[TRANSLATE_X] = style_unit::PX;
+    node
+  }
+
+  #[test]
+  fn transition_start() {
+    let mut node = create();
+    node.set_transition(TRANSLATE_X, 100.0, 0.0, LINEAR);
+    assert!(!node.equal_set_style(TRANSLATE_X, 100.0, style_unit::PX));
+    // 值由动画驱动，不立即生效
+    assert_eq!(node.current_style[TRANSLATE_X], 0.0);
+    assert_eq!(node.implicit.len(), 1);
+    node.before(0.0);
+    node.after();
+    node.before(50.0);
+    node.after();
+    assert_eq!(node.current_style[TRANSLATE_X], 50.0);
+    node.before(50.0);
+    node.after();
+    assert_eq!(node.current_style[TRANSLATE_X], 100.0);
+    assert!(node.implicit.is_empty());
+  }
+
+  #[test]
+  fn transition_interrupt() {
+    let mut node = create();
+    node.set_transition(TRANSLATE_X, 100.0, 0.0, LINEAR);
+    node.equal_set_style(TRANSLATE_X, 100.0, style_unit::PX);
+    node.before(0.0);
+    node.after();
+    node.before(50.0);
+    node.after();
+    // 同目标值继续运行
+    node.equal_set_style(TRANSLATE_X, 100.0, style_unit::PX);
+    assert_eq!(node.implicit.len(), 1);
+    assert_eq!(node.implicit[0].current_time, 50.0);
+    // 新目标从当前值开始，替换掉原有的
+    node.equal_set_style(TRANSLATE_X, 0.0, style_unit::PX);
+    assert_eq!(node.implicit.len(), 1);
+    assert_eq!(node.implicit[0].current_time, 0.0);
+    node.before(0.0);
+    node.after();
+    assert_eq!(node.current_style[TRANSLATE_X], 50.0);
+    node.before(100.0);
+    node.after();
+    assert_eq!(node.current_style[TRANSLATE_X], 0.0);
+    assert!(node.implicit.is_empty());
+  }
+
+  #[test]
+  fn transition_zero_duration() {
+    let mut node = create();
+    node.set_transition(TRANSLATE_X, 100.0, 0.0, LINEAR);
+    node.set_transition(TRANSLATE_X, 0.0, 0.0, LINEAR);
+    node.equal_set_style(TRANSLATE_X, 100.0, style_unit::PX);
+    assert_eq!(node.current_style[TRANSLATE_X], 100.0);
+    assert!(node.implicit.is_empty());
+    // 单位不同同样立即生效
+    node.set_transition(TRANSLATE_X, 100.0, 0.0, LINEAR);
+    node.update_style(TRANSLATE_X, 10.0, style_unit::PERCENT);
+    assert_eq!(node.current_style[TRANSLATE_X], 10.0);
+    assert!(node.implicit.is_empty());
+  }
+
+  #[test]
+  fn composite_base() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    unsafe { &mut *node }.update_style(TRANSLATE_X, 10.0, style_unit::PX);
+    let ani = add_ani(node, 100.0, FORWARDS, 1.0, 1);
+    get(ani).composite = ADD;
+    get(ani).play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 50.0);
+    assert_eq!(translate_x(node), 60.0);
+    // 修改基础样式后重新叠加
+    unsafe { &mut *node }.update_style(TRANSLATE_X, 20.0, style_unit::PX);
+    assert_eq!(translate_x(node), 70.0);
+    assert!(unsafe { &mut *node }.equal_set_style(TRANSLATE_X, 20.0, style_unit::PX));
+    assert_eq!(translate_x(node), 70.0);
+    // 移除后恢复基础样式
+    unsafe { &mut *node }.remove_ani(ani);
+    assert_eq!(translate_x(node), 20.0);
+    assert!(unsafe { &mut *node }.animations.is_empty());
+  }
+
+  #[test]
+  fn composite_calc() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+    unsafe { &mut *node }.update_style(TRANSLATE_X, 10.0, style_unit::PX);
+    let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 1, 0.0, 0.0, LINEAR);
+    let u = vec![style_unit::PX, style_unit::PERCENT];
+    ani.add_frame(false, 0.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_calc(false, TRANSLATE_X, vec![10.0, 50.0], u.clone(), vec![10.0, 0.0]);
+    ani.add_frame(false, 100.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_calc(false, TRANSLATE_X, vec![20.0, 50.0], u.clone(), vec![0.0, 0.0]);
+    ani.composite = ADD;
+    let ani = wasm_ptr::from_rust(ani);
+    unsafe { &mut *node }.add_ani(ani);
+    get(ani).play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 50.0);
+    // 单位不同无法叠加，同replace使用动画的calc
+    let node = unsafe { &*node };
+    assert_eq!(node.current_unit[TRANSLATE_X], style_unit::CALC);
+    assert!(node.equal_calc(TRANSLATE_X, &to_calc(&[15.0, 50.0], &u)));
+  }
+
+  #[test]
+  fn calc_size() {
+    let mut node = Node::new(false);
+    node.set_xywh(0.0, 0.0, 100.0, 50.0);
+    node.set_font_size(16.0, style_unit::PX);
+    node.set_calc(TRANSLATE_X, vec![50.0, -10.0], vec![style_unit::PERCENT, style_unit::PX]);
+    node.set_calc(TFO_X, vec![50.0, 10.0], vec![style_unit::PERCENT, style_unit::PX]);
+    node.cal_matrix(refresh_level::REFLOW);
+    assert_eq!(node.computed_style[TRANSLATE_X], 40.0);
+    assert_eq!(node.computed_style[TFO_X], 60.0);
+    // 各项分别换算后相加
+    node.set_calc(TRANSLATE_Y, vec![1.0, 10.0, 2.0], vec![style_unit::EM, style_unit::PERCENT, style_unit::PX]);
+    assert_eq!(node.cal_size(TRANSLATE_Y, 0.0, style_unit::CALC, node.offset_height), 23.0);
+    assert!(node.equal_set_calc(TRANSLATE_Y, vec![1.0, 10.0, 2.0], vec![style_unit::EM, style_unit::PERCENT, style_unit::PX]));
+    assert!(!node.equal_set_calc(TRANSLATE_Y, vec![1.0], vec![style_unit::EM]));
+  }
+
+  #[test]
+  fn calc_interpolate() {
+    let mut root = Root::new();
+    let node = add_node(&mut root, 0, 0.0, 0.0, 100.0, 10.0);
+    let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 1, 0.0, 0.0, LINEAR);
+    let u = vec![style_unit::PERCENT, style_unit::PX];
+    ani.add_frame(false, 0.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_calc(false, TRANSLATE_X, vec![0.0, 0.0], u.clone(), vec![100.0, -20.0]);
+    ani.add_frame(false, 100.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+    ani.add_item_calc(false, TRANSLATE_X, vec![100.0, -20.0], u.clone(), vec![0.0, 0.0]);
+    let ani = wasm_ptr::from_rust(ani);
+    unsafe { &mut *node }.add_ani(ani);
+    get(ani).play();
+    tick(&mut root, 0.0);
+    tick(&mut root, 25.0);
+    let node = unsafe { &*node };
+    assert!(node.equal_calc(TRANSLATE_X, &to_calc(&[25.0, -5.0], &u)));
+    assert_eq!(node.computed_style[TRANSLATE_X], 20.0);
+    tick(&mut root, 75.0);
+    assert!(node.equal_calc(TRANSLATE_X, &to_calc(&[100.0, -20.0], &u)));
+    assert_eq!(node.computed_style[TRANSLATE_X], 80.0);
+  }
+
+  #[test]
+  fn composite_scale() {
+    for (composite, r) in [(ADD, 3.0), (ACCUMULATE, 2.5)] {
+      let mut root = Root::new();
+      let node = add_node(&mut root, 0, 0.0, 0.0, 10.0, 10.0);
+      unsafe { &mut *node }.update_style(SCALE_X, 1.5, style_unit::NUMBER);
+      let mut ani = Animation::from_node(node, NORMAL, 100.0, 0, 0.0, 0.0, FORWARDS, 1.0, 1, 0.0, 0.0, LINEAR);
+      ani.add_frame(false, 0.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+      ani.add_item(false, SCALE_X, 2.0, style_unit::NUMBER, 0.0);
+      ani.add_frame(false, 100.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+      ani.add_item(false, SCALE_X, 2.0, style_unit::NUMBER, 0.0);
+      ani.composite = composite;
+      let ani = wasm_ptr::from_rust(ani);
+      unsafe { &mut *node }.add_ani(ani);
+      get(ani).play();
+      tick(&mut root, 0.0);
+      // add相乘，accumulate以1为基准相加
+      assert_eq!(unsafe { &*node }.current_style[SCALE_X], r);
+    }
   }
 }
@@ -1,9 +1,13 @@
 use std::f64;
+use std::cmp;
+use std::ptr;
 use wasm_bindgen::prelude::*;
 use crate::{wasm_ptr};
-use crate::math::{assign_m, multiply2};
+use crate::math::{identity, is_e, assign_m, multiply2, cal_rect_point, cal_bbox, inverse_point};
 use crate::node::Node;
+use crate::animation::AnimationGroup;
 use crate::refresh::refresh_level;
+use crate::style::style_unit;
 
 pub const CANVAS: u8 = 0;
 pub const WEBGL: u8 = 2;
@@ -19,7 +23,12 @@ pub struct Root {
   me: Vec<[f64; 16]>,
   op: Vec<f64>,
   vt: Vec<[f64; 16]>,
-  am_states: Vec<u8>,
+  bbox: Vec<[f64; 4]>, // 节点在屏幕上的轴对齐包围盒，xa/ya/xb/yb
+  dirty: Vec<[f64; 4]>, // canvas模式下本帧需重绘的区域，互相不重叠
+  removed: Vec<[f64; 4]>, // 两次refresh之间被移除节点的包围盒，下次refresh并入dirty
+  last_mode: u8, // 上次refresh的mode，切换后vt等需全部重新计算
+  events: Vec<[f64; 4]>, // 本帧的动画事件，类型、动画id、轮次、current_time
+  groups: Vec<*mut AnimationGroup>,
 }
 
 #[wasm_bindgen]
@@ -35,7 +44,12 @@ impl Root {
       me: Vec::new(),
       op: Vec::new(),
       vt: Vec::new(),
-      am_states: Vec::new(),
+      bbox: Vec::new(),
+      dirty: Vec::new(),
+      removed: Vec::new(),
+      last_mode: 0,
+      events: Vec::new(),
+      groups: Vec::new(),
     }
   }
 
@@ -44,20 +58,185 @@ impl Root {
     self.nodes.push(node);
     let node = unsafe { &mut *node };
     node.set_root(self);
+    // 新节点没有上一帧的数据，需强制计算
+    node.refresh_level |= refresh_level::REFLOW;
+    node.total = 0;
+    let i = self.nodes.len() - 1;
+    self.update_total(i, node.lv, 1);
+    node.parent = self.find_parent(i, node.lv);
+    node.cal_font_size();
   }
 
+  // 只移除单个节点，子孙节点上提一层，直接子节点改为挂在其父节点上
   pub fn remove_node(&mut self, i: usize) -> () {
+    let node = unsafe { &mut *self.nodes[i] };
+    let end = cmp::min(i + node.total + 1, self.nodes.len());
+    for item in self.nodes[i + 1..end].iter() {
+      let child = unsafe { &mut **item };
+      if child.lv == node.lv + 1 {
+        child.parent = node.parent;
+      }
+      child.lv -= 1;
+      // 继承关系改变，需重新计算
+      child.refresh_level |= refresh_level::REFLOW;
+    }
+    self.update_total(i, node.lv, -1);
+    node.set_root(ptr::null_mut());
+    node.parent = ptr::null_mut();
+    node.total = 0;
     self.nodes.remove(i);
+    self.remove_buffers(i, 1);
   }
 
   pub fn set_node(&mut self, i: usize, node: *mut Node) -> () {
+    let node = wasm_ptr::transform_mut(node);
+    let old = unsafe { &mut *self.nodes[i] };
+    old.set_root(ptr::null_mut());
     self.nodes[i] = node;
+    if i < self.bbox.len() {
+      self.removed.push(self.bbox[i]);
+      self.bbox[i] = [0.0, 0.0, 0.0, 0.0];
+    }
+    let p = node;
+    let node = unsafe { &mut *node };
+    node.set_root(self);
+    node.refresh_level |= refresh_level::REFLOW;
+    // 替换不改变结构，沿用原节点的total和parent，子节点的parent指向新节点
+    node.total = old.total;
+    node.parent = old.parent;
+    old.parent = ptr::null_mut();
+    let end = cmp::min(i + node.total + 1, self.nodes.len());
+    for item in self.nodes[i + 1..end].iter() {
+      let child = unsafe { &mut **item };
+      if child.lv == node.lv + 1 {
+        child.parent = p;
+      }
+    }
+    node.cal_font_size();
   }
 
   pub fn insert_node(&mut self, i: usize, node: *mut Node) -> () {
+    let node = wasm_ptr::transform_mut(node);
     self.nodes.insert(i, node);
+    self.insert_buffers(i, 1);
     let node = unsafe { &mut *node };
     node.set_root(self);
+    node.refresh_level |= refresh_level::REFLOW;
+    node.total = 0;
+    self.update_total(i, node.lv, 1);
+    node.parent = self.find_parent(i, node.lv);
+    node.cal_font_size();
+  }
+
+  // 在at处插入一整棵先序遍历的子树，第0个为子树根节点，lv需js提前设置好
+  pub fn insert_subtree(&mut self, at: usize, nodes: Vec<usize>) -> () {
+    let len = nodes.len();
+    if len == 0 {
+      return
+    }
+    let mut list: Vec<*mut Node> = nodes.iter().map(|&x| wasm_ptr::transform_mut(x as *mut Node)).collect();
+    let lv = unsafe { (*list[0]).lv };
+    let top = self.find_parent(at, lv);
+    // 子树内部的total用栈计算，每个节点出栈时其total即为期间经过的节点数，栈顶即为parent
+    let mut stack: Vec<usize> = Vec::new();
+    let mut count = 0;
+    while count < len {
+      let node = unsafe { &mut *list[count] };
+      while let Some(&j) = stack.last() {
+        let p = unsafe { &mut *list[j] };
+        if p.lv < node.lv {
+          break
+        }
+        p.total = count - j - 1;
+        stack.pop();
+      }
+      node.parent = match stack.last() {
+        Some(&j) => list[j],
+        None => top,
+      };
+      node.set_root(self);
+      node.refresh_level |= refresh_level::REFLOW;
+      stack.push(count);
+      count += 1;
+    }
+    for j in stack.iter() {
+      let p = unsafe { &mut *list[*j] };
+      p.total = len - j - 1;
+    }
+    // 先序保证父节点先于子节点计算
+    for item in list.iter() {
+      let node = unsafe { &mut **item };
+      node.cal_font_size();
+    }
+    self.nodes.splice(at..at, list);
+    self.insert_buffers(at, len);
+    self.update_total(at, lv, len as isize);
+  }
+
+  // 移除at处节点及其所有子孙，返回移除的数量
+  pub fn remove_subtree(&mut self, at: usize) -> usize {
+    let node = unsafe { & *self.nodes[at] };
+    let n = cmp::min(node.total + 1, self.nodes.len() - at);
+    self.update_total(at, node.lv, -(n as isize));
+    for item in self.nodes.drain(at..at + n) {
+      let node = unsafe { &mut *item };
+      node.set_root(ptr::null_mut());
+      node.parent = ptr::null_mut();
+    }
+    self.remove_buffers(at, n);
+    n
+  }
+
+  // 各缓存数据保持和节点列表对齐，后续未变化的节点才能复用
+  fn insert_buffers(&mut self, i: usize, n: usize) -> () {
+    if i > self.bbox.len() {
+      return
+    }
+    self.rl.splice(i..i, vec![refresh_level::NONE; n]);
+    self.me.splice(i..i, vec![identity(); n]);
+    self.op.splice(i..i, vec![1.0; n]);
+    self.vt.splice(i..i, vec![[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]; n]);
+    self.bbox.splice(i..i, vec![[0.0, 0.0, 0.0, 0.0]; n]);
+  }
+
+  // 移除的区域需要重绘
+  fn remove_buffers(&mut self, i: usize, n: usize) -> () {
+    if i >= self.bbox.len() {
+      return
+    }
+    let end = cmp::min(i + n, self.bbox.len());
+    self.rl.drain(i..end);
+    self.me.drain(i..end);
+    self.op.drain(i..end);
+    self.vt.drain(i..end);
+    self.removed.extend(self.bbox.drain(i..end));
+  }
+
+  // 第i个位置的lv层级节点的父节点，即之前第一个lv更小的
+  fn find_parent(&self, i: usize, lv: usize) -> *mut Node {
+    let mut count = i;
+    while count > 0 && lv > 0 {
+      count -= 1;
+      let node = self.nodes[count];
+      if unsafe { (*node).lv } < lv {
+        return node
+      }
+    }
+    ptr::null_mut()
+  }
+
+  // 第i个位置增删n个lv层级的节点后，更新其之前的所有祖先节点的total
+  fn update_total(&mut self, i: usize, lv: usize, n: isize) -> () {
+    let mut lv = lv;
+    let mut count = i;
+    while count > 0 && lv > 0 {
+      count -= 1;
+      let node = unsafe { &mut *self.nodes[count] };
+      if node.lv < lv {
+        lv = node.lv;
+        node.total = (node.total as isize + n) as usize;
+      }
+    }
   }
 
   pub fn clear(&mut self) -> () {
@@ -66,19 +245,68 @@ impl Root {
     self.me.clear();
     self.op.clear();
     self.vt.clear();
+    self.bbox.clear();
+    self.dirty.clear();
+    self.removed.clear();
   }
 
   pub fn size(&self) -> usize {
     self.nodes.len()
   }
 
+  // 尺寸变化后，vw/vh等单位的样式自动重新计算
   pub fn resize(&mut self, width: f64, height: f64) -> () {
+    if self.width == width && self.height == height {
+      return
+    }
     self.width = width;
     self.height = height;
+    self.recal_unit(|u| u == style_unit::VW || u == style_unit::VH
+      || u == style_unit::VMAX || u == style_unit::VMIN);
+  }
+
+  // rem的基准变化后，rem单位的样式自动重新计算
+  pub fn set_font_size(&mut self, v: f64) -> () {
+    if self.font_size == v {
+      return
+    }
+    self.font_size = v;
+    self.recal_unit(|u| u == style_unit::REM);
+  }
+
+  // 先序遍历，父节点的font-size先于子节点更新，font-size变化的需同时重算em单位的样式，
+  // inherit单位的样式在refresh时根据父节点的变化更新
+  fn recal_unit(&mut self, f: fn(usize) -> bool) -> () {
+    let mut count = 0;
+    let len = self.nodes.len();
+    while count < len {
+      let node = unsafe { &mut *self.nodes[count] };
+      let old = node.font_size;
+      node.cal_font_size();
+      if !node.is_text {
+        if node.font_size != old {
+          node.recal_unit(|u| u == style_unit::EM);
+        }
+        node.recal_unit(f);
+      }
+      count += 1;
+    }
+  }
+
+  pub fn add_group(&mut self, group: *mut AnimationGroup) -> () {
+    let group = wasm_ptr::transform_mut(group);
+    self.groups.push(group);
+  }
+
+  pub fn remove_group(&mut self, group: *mut AnimationGroup) -> () {
+    let group = wasm_ptr::transform_mut(group);
+    self.groups.retain(|&x| x != group);
   }
 
   // 每帧raf优先存调用，传入运行时间，后续节点动画来计算transition，返回需要刷新动画的数量
+  // 上一帧的事件在此清空，帧之间调用cancel等产生的事件可在调用后立刻读取
   pub fn before(&mut self, diff: f64) -> usize {
+    self.events.clear();
     let mut count = 0;
     let mut res = 0;
     let len = self.nodes.len();
@@ -87,6 +315,12 @@ impl Root {
       res += node.before(diff);
       count += 1;
     }
+    for group in self.groups.iter() {
+      let group = unsafe { &mut **group };
+      if group.before(diff) {
+        res += 1;
+      }
+    }
     res
   }
 
@@ -95,20 +329,32 @@ impl Root {
     let mut count = 0;
     let mut res = 0;
     let len = self.nodes.len();
-    self.am_states.clear();
     while count < len {
       let node = unsafe { &mut *self.nodes[count] };
       res += node.after();
       count += 1;
     }
+    // group子动画的事件排在所有节点之后
+    let groups = self.groups.clone();
+    for group in groups.iter() {
+      let group = unsafe { &mut **group };
+      if group.after() {
+        res += 1;
+      }
+    }
     res
   }
 
-  pub fn add_am_state(&mut self, n: u8) -> () {
-    self.am_states.push(n);
+  pub(crate) fn add_event(&mut self, kind: u8, id: usize, iteration: usize, time: f64) -> () {
+    self.events.push([kind as f64, id as f64, iteration as f64, time]);
+  }
+
+  pub fn clear_events(&mut self) -> () {
+    self.events.clear();
   }
 
-  // 每帧刷新前调用，计算节点列表的matrix和opacity
+  // 每帧刷新前调用，计算节点列表的matrix和opacity，
+  // 只有自身refresh_level非0或祖先有变化的节点才重新计算，其它复用上一帧的数据
   pub fn refresh(&mut self) -> () {
     let mut count = 0;
     let len = self.nodes.len();
@@ -116,6 +362,21 @@ impl Root {
     self.me.resize(len, [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
     self.op.resize(len, 1.0);
     self.vt.resize(len, [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    self.bbox.resize(len, [0.0, 0.0, 0.0, 0.0]);
+    let is_canvas = self.mode == CANVAS;
+    let force = self.mode != self.last_mode;
+    self.last_mode = self.mode;
+    self.dirty.clear();
+    if is_canvas {
+      let removed = std::mem::take(&mut self.removed);
+      for b in removed.iter() {
+        self.add_dirty(b);
+      }
+    } else {
+      self.removed.clear();
+    }
+    // 节点自身refresh_level非0或者祖先有变化都视为有变化，canvas需要重绘前后两个位置
+    let mut changed: Vec<bool> = vec![false; len];
     let mut p_list: Vec<usize> = Vec::new();
     let mut last_lv: usize = 0;
     let mut parent: usize = 0; // 存下标，取op/me上的
@@ -123,6 +384,8 @@ impl Root {
     let root = unsafe { &mut *self.nodes[0] };
     let cx = root.offset_width * 0.5;
     let cy = root.offset_height * 0.5;
+    // webgl的z轴按较大的半边长归一化
+    let tz = f64::max(cx, cy);
     // 节点列表
     while count < len {
       let node = unsafe { &mut *self.nodes[count] };
@@ -142,40 +405,154 @@ impl Root {
       last_lv = lv;
       // 不变是sibling无需特殊处理
       // else {}
-      let (m1, m2) = unsafe {
-        (
-          & *(node.m_ptr() as *const [f64; 16] as *mut [f64; 16]),
-          &mut *(node.me_ptr() as *const [f64; 16] as *mut [f64; 16]),
-        )
-      };
-      // 除了root的子节点需要预乘matrix
-      if count == 0 {
-        assign_m(m2, m1);
-        node.opacity = node.get_op();
-        self.rl[count] = node.refresh_level;
-        assign_m(&mut self.me[count], m2);
-        self.op[count] = node.opacity;
-      }
-      // 文字节点直接用父的matrix和opacity
-      else if node.is_text {
-        let p = unsafe { & *self.nodes[parent] };
-        let pm = unsafe { & *(p.me_ptr() as *const [f64; 16] as *mut [f64; 16]) };
-        self.rl[count] = p.refresh_level;
-        assign_m(&mut self.me[count], pm);
-        self.op[count] = p.opacity;
-      }
-      else {
-        let p = unsafe { & *self.nodes[parent] };
-        let pm = unsafe { & *(p.me_ptr() as *const [f64; 16] as *mut [f64; 16]) };
-        multiply2(pm, m1, m2);
-        node.opacity = p.opacity * node.get_op();
-        self.rl[count] = node.refresh_level;
-        assign_m(&mut self.me[count], m2);
-        self.op[count] = node.opacity;
-      }
-      // 和js不同不跳total，因为matrix等所有数据都存在这里只一份
+      changed[count] = force || node.refresh_level != refresh_level::NONE || (count > 0 && changed[parent]);
+      if !changed[count] {
+        self.rl[count] = refresh_level::NONE;
+      } else {
+        // 父节点的font-size可能变化，继承的font-size和em单位的样式随之更新
+        if count > 0 && !node.is_text {
+          let old = node.font_size;
+          node.cal_font_size();
+          if node.font_size != old {
+            node.recal_unit(|u| u == style_unit::EM);
+          }
+        }
+        // 父节点变化后，inherit单位的样式需重新取父节点的计算值
+        if count > 0 && !node.is_text && self.rl[parent] != refresh_level::NONE {
+          node.recal_unit(|u| u == style_unit::INHERIT);
+        }
+        let (m1, m2) = unsafe {
+          (
+            & *(node.m_ptr() as *const [f64; 16] as *mut [f64; 16]),
+            &mut *(node.me_ptr() as *const [f64; 16] as *mut [f64; 16]),
+          )
+        };
+        // 除了root的子节点需要预乘matrix
+        if count == 0 {
+          assign_m(m2, m1);
+          node.opacity = node.get_op();
+          self.rl[count] = node.refresh_level;
+          assign_m(&mut self.me[count], m2);
+          self.op[count] = node.opacity;
+        }
+        // 文字节点直接用父的matrix和opacity，父的refresh_level已被重置，取本帧记录的
+        else if node.is_text {
+          let p = unsafe { & *self.nodes[parent] };
+          let pm = unsafe { & *(p.me_ptr() as *const [f64; 16] as *mut [f64; 16]) };
+          self.rl[count] = self.rl[parent];
+          assign_m(&mut self.me[count], pm);
+          self.op[count] = p.opacity;
+        }
+        else {
+          let p = unsafe { & *self.nodes[parent] };
+          let pm = unsafe { & *(p.me_ptr() as *const [f64; 16] as *mut [f64; 16]) };
+          let pp = unsafe { & *(p.pm_ptr() as *const [f64; 16]) };
+          // 父节点有perspective时需先乘上
+          if is_e(pp) {
+            multiply2(pm, m1, m2);
+          } else {
+            let mut t = identity();
+            multiply2(pm, pp, &mut t);
+            multiply2(&t, m1, m2);
+          }
+          node.opacity = p.opacity * node.get_op();
+          self.rl[count] = node.refresh_level;
+          assign_m(&mut self.me[count], m2);
+          self.op[count] = node.opacity;
+        }
+        // 本帧已消费，重置等待下次变化
+        node.refresh_level = refresh_level::NONE;
+        let p = cal_rect_point(node.xa, node.ya, node.xb, node.yb, &self.me[count]);
+        let b = cal_bbox(&p);
+        if is_canvas {
+          let prev = self.bbox[count];
+          self.add_dirty(&prev);
+          self.add_dirty(&b);
+        }
+        self.bbox[count] = b;
+        // webgl模式下顶点坐标直接在这里算好，js无需再计算
+        if self.mode == WEBGL {
+          let vt = &mut self.vt[count];
+          let mut i = 0;
+          while i < 16 {
+            let (x, y, z, w) = convert_coords2_gl(p[i], p[i + 1], p[i + 2], p[i + 3], cx, cy, tz);
+            vt[i] = x;
+            vt[i + 1] = y;
+            vt[i + 2] = z;
+            vt[i + 3] = w;
+            i += 4;
+          }
+        }
+      }
+      // opacity为0的子树不可见，直接跳过total，子孙节点等到重新可见时父节点变化会触发重新计算
+      if !node.is_text && node.opacity == 0.0 && node.total > 0 {
+        let end = cmp::min(count + node.total + 1, len);
+        // 刚变为不可见时，子孙节点原本的位置需重绘，并清空数据防止js使用过期的
+        if changed[count] {
+          let mut i = count + 1;
+          while i < end {
+            if is_canvas {
+              let prev = self.bbox[i];
+              self.add_dirty(&prev);
+            }
+            self.rl[i] = refresh_level::NONE;
+            self.op[i] = 0.0;
+            self.bbox[i] = [0.0, 0.0, 0.0, 0.0];
+            i += 1;
+          }
+        }
+        count = end;
+        continue
+      }
+      count += 1;
+    }
+  }
+
+  // 返回点所在的最上层非文字节点下标，没有返回-1，需在refresh之后调用
+  // 先序遍历越往后的节点越在上层，子孙在自身之上，顺序查找最后一个命中的即可
+  // opacity为0的子树在refresh中被跳过，matrix不是最新的，按total直接跳过
+  pub fn hit_test(&self, x: f64, y: f64) -> isize {
+    let mut res = -1;
+    let mut count = 0;
+    let len = self.nodes.len();
+    while count < len {
+      let node = unsafe { & *self.nodes[count] };
+      if node.opacity == 0.0 {
+        count += node.total + 1;
+        continue
+      }
+      if !node.is_text {
+        if let Some((x, y)) = inverse_point(x, y, &node.matrix_event) {
+          if x >= node.xa && x <= node.xb && y >= node.ya && y <= node.yb {
+            res = count as isize;
+          }
+        }
+      }
       count += 1;
     }
+    res
+  }
+
+  // 加入一个重绘区域，和已有的重叠时合并，直到没有重叠为止
+  fn add_dirty(&mut self, b: &[f64; 4]) -> () {
+    if b[2] <= b[0] || b[3] <= b[1] {
+      return
+    }
+    // 抗锯齿边缘会溢出小数部分，取整外扩
+    let mut r = [b[0].floor(), b[1].floor(), b[2].ceil(), b[3].ceil()];
+    let mut i = 0;
+    while i < self.dirty.len() {
+      let d = self.dirty[i];
+      if d[0] <= r[2] && r[0] <= d[2] && d[1] <= r[3] && r[1] <= d[3] {
+        r = [f64::min(d[0], r[0]), f64::min(d[1], r[1]), f64::max(d[2], r[2]), f64::max(d[3], r[3])];
+        self.dirty.swap_remove(i);
+        // 合并后变大可能和之前检查过的重叠，重新开始
+        i = 0;
+      } else {
+        i += 1;
+      }
+    }
+    self.dirty.push(r);
   }
 
   pub fn rl_ptr(&self) -> *const usize {
@@ -194,8 +571,24 @@ impl Root {
     self.vt.as_ptr()
   }
 
-  pub fn am_states_ptr(&self) -> *const u8 {
-    self.am_states.as_ptr()
+  pub fn bbox_ptr(&self) -> *const [f64; 4] {
+    self.bbox.as_ptr()
+  }
+
+  pub fn dirty_ptr(&self) -> *const [f64; 4] {
+    self.dirty.as_ptr()
+  }
+
+  pub fn dirty_size(&self) -> usize {
+    self.dirty.len()
+  }
+
+  pub fn events_ptr(&self) -> *const [f64; 4] {
+    self.events.as_ptr()
+  }
+
+  pub fn events_size(&self) -> usize {
+    self.events.len()
   }
 }
 
@@ -225,3 +618,293 @@ fn convert_coords2_gl(mut x: f64, mut y: f64, mut z: f64, w: f64, cx: f64, cy: f
   }
   (x, y, z, w)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::{new_node, add_node as add};
+  use crate::style::style_key::{TRANSLATE_X, TRANSLATE_Y, TFO_X, TFO_Y, ROTATE_Z};
+
+  // 同js修改样式后计算matrix
+  fn move_x(node: *mut Node, v: f64) -> () {
+    let node = unsafe { &mut *node };
+    node.equal_set_style(TRANSLATE_X, v, style_unit::PX);
+    node.cal_matrix(node.get_rl());
+  }
+
+  #[test]
+  fn hit_test_order() {
+    let mut root = Root::new();
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 0.0, 0.0, 50.0, 50.0);
+    add(&mut root, 2, 10.0, 10.0, 10.0, 10.0);
+    let b = add(&mut root, 1, 5.0, 5.0, 40.0, 40.0);
+    root.refresh();
+    // 后面的兄弟在上层，子孙在自身之上
+    assert_eq!(root.hit_test(15.0, 15.0), 3);
+    assert_eq!(root.hit_test(2.0, 2.0), 1);
+    assert_eq!(root.hit_test(80.0, 80.0), 0);
+    assert_eq!(root.hit_test(200.0, 200.0), -1);
+    unsafe { (*b).opacity = 0.0 };
+    assert_eq!(root.hit_test(15.0, 15.0), 2);
+    // 不可见的子树整体跳过，子孙的opacity可能是过期的
+    unsafe {
+      (*b).opacity = 1.0;
+      (*a).opacity = 0.0;
+    }
+    assert_eq!(root.hit_test(2.0, 2.0), 0);
+    assert_eq!(root.hit_test(47.0, 47.0), 0);
+    assert_eq!(root.hit_test(15.0, 15.0), 3);
+  }
+
+  #[test]
+  fn font_size_refresh() {
+    let mut root = Root::new();
+    root.set_font_size(16.0);
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 0.0, 0.0, 50.0, 50.0);
+    let b = add(&mut root, 2, 0.0, 0.0, 10.0, 10.0);
+    let c = add(&mut root, 3, 0.0, 0.0, 10.0, 10.0);
+    unsafe { (*c).set_font_size(0.5, style_unit::EM) };
+    root.refresh();
+    assert_eq!(unsafe { (*c).font_size }, 8.0);
+    unsafe { (*a).set_font_size(2.0, style_unit::EM) };
+    assert_eq!(unsafe { (*a).font_size }, 32.0);
+    // 子孙节点在refresh时更新
+    root.refresh();
+    assert_eq!(unsafe { (*b).font_size }, 32.0);
+    assert_eq!(unsafe { (*c).font_size }, 16.0);
+    // 未添加到root的使用默认值
+    let mut node = Node::new(false);
+    node.set_font_size(2.0, style_unit::EM);
+    assert_eq!(node.font_size, 32.0);
+  }
+
+  #[test]
+  fn remove_node_reparent() {
+    let mut root = Root::new();
+    root.set_font_size(16.0);
+    let r = add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 0.0, 0.0, 50.0, 50.0);
+    let b = add(&mut root, 2, 0.0, 0.0, 10.0, 10.0);
+    let c = add(&mut root, 3, 0.0, 0.0, 10.0, 10.0);
+    add(&mut root, 1, 0.0, 0.0, 10.0, 10.0);
+    unsafe { (*a).set_font_size(2.0, style_unit::EM) };
+    root.refresh();
+    assert_eq!(unsafe { (*c).font_size }, 32.0);
+    root.remove_node(1);
+    assert_eq!(root.size(), 4);
+    unsafe {
+      assert_eq!((*r).total, 3);
+      assert_eq!((*b).lv, 1);
+      assert_eq!((*b).parent, r);
+      assert_eq!((*c).lv, 2);
+      assert_eq!((*c).parent, b);
+      assert!((*a).root.is_null());
+    }
+    // 原本继承自a的font-size改为继承自root
+    root.refresh();
+    assert_eq!(unsafe { (*c).font_size }, 16.0);
+  }
+
+  #[test]
+  fn font_size_units() {
+    let mut root = Root::new();
+    root.set_font_size(20.0);
+    root.resize(200.0, 100.0);
+    let a = add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let b = add(&mut root, 1, 0.0, 0.0, 50.0, 50.0);
+    let a = unsafe { &mut *a };
+    let b = unsafe { &mut *b };
+    for (v, u, r) in [(2.0, style_unit::REM, 40.0), (10.0, style_unit::VW, 20.0), (10.0, style_unit::VH, 10.0),
+      (10.0, style_unit::VMAX, 20.0), (10.0, style_unit::VMIN, 10.0), (12.0, style_unit::PX, 12.0)] {
+      a.set_font_size(v, u);
+      assert_eq!(a.font_size, r);
+    }
+    b.set_font_size(50.0, style_unit::PERCENT);
+    assert_eq!(b.font_size, 6.0);
+    // 不支持calc，同inherit
+    b.set_font_size(0.0, style_unit::CALC);
+    assert_eq!(b.font_size, 12.0);
+    // 未添加到root的rem使用默认值，视口尺寸为0
+    let mut node = Node::new(false);
+    node.set_font_size(2.0, style_unit::REM);
+    assert_eq!(node.font_size, 32.0);
+    node.set_font_size(10.0, style_unit::VW);
+    assert_eq!(node.font_size, 0.0);
+  }
+
+  #[test]
+  fn to_local_rotate() {
+    let mut root = Root::new();
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = unsafe { &mut *add(&mut root, 1, 10.0, 10.0, 40.0, 20.0) };
+    a.equal_set_style(TFO_X, 0.0, style_unit::PX);
+    a.equal_set_style(TFO_Y, 0.0, style_unit::PX);
+    a.equal_set_style(ROTATE_Z, 90.0, style_unit::DEG);
+    a.cal_matrix(a.get_rl());
+    root.refresh();
+    let p = a.to_local(5.0, 30.0).unwrap();
+    // 绕左上角旋转90度，(5, 30)对应旋转前的(30, 15)
+    assert!((p[0] - 30.0).abs() < 1e-9 && (p[1] - 15.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn dirty_merge() {
+    let mut root = Root::new();
+    root.add_dirty(&[0.0, 0.0, 10.0, 10.0]);
+    root.add_dirty(&[20.0, 20.0, 30.0, 30.0]);
+    // 空区域忽略
+    root.add_dirty(&[40.0, 40.0, 40.0, 50.0]);
+    assert_eq!(root.dirty, vec![[0.0, 0.0, 10.0, 10.0], [20.0, 20.0, 30.0, 30.0]]);
+    // 同时和两个重叠，合并为一个，小数外扩取整
+    root.add_dirty(&[5.5, 5.5, 25.5, 25.5]);
+    assert_eq!(root.dirty, vec![[0.0, 0.0, 30.0, 30.0]]);
+    root.add_dirty(&[31.0, 0.0, 32.0, 1.0]);
+    assert_eq!(root.dirty_size(), 2);
+  }
+
+  #[test]
+  fn dirty_refresh() {
+    let mut root = Root::new();
+    root.mode = CANVAS;
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 10.0, 10.0, 10.0, 10.0);
+    add(&mut root, 1, 50.0, 50.0, 10.0, 10.0);
+    root.refresh();
+    assert_eq!(root.dirty, vec![[0.0, 0.0, 100.0, 100.0]]);
+    root.refresh();
+    assert!(root.dirty.is_empty());
+    // 前后两个位置合并
+    move_x(a, 5.0);
+    root.refresh();
+    assert_eq!(root.dirty, vec![[10.0, 10.0, 25.0, 20.0]]);
+    // 移除的节点下次refresh时重绘原本的位置
+    root.remove_node(2);
+    root.refresh();
+    assert_eq!(root.dirty, vec![[50.0, 50.0, 60.0, 60.0]]);
+    root.refresh();
+    assert!(root.dirty.is_empty());
+    // webgl模式不记录
+    root.mode = WEBGL;
+    move_x(a, 0.0);
+    root.refresh();
+    assert!(root.dirty.is_empty());
+  }
+
+  #[test]
+  fn refresh_incremental() {
+    let mut root = Root::new();
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 10.0, 10.0, 50.0, 50.0);
+    add(&mut root, 2, 10.0, 10.0, 10.0, 10.0);
+    add(&mut root, 1, 70.0, 70.0, 10.0, 10.0);
+    root.refresh();
+    assert!(root.rl.iter().all(|&x| x != refresh_level::NONE));
+    // 无变化全部复用
+    root.refresh();
+    assert!(root.rl.iter().all(|&x| x == refresh_level::NONE));
+    // 祖先变化的子孙也重新计算，兄弟复用
+    move_x(a, 5.0);
+    let me = root.me[3];
+    root.refresh();
+    assert_ne!(root.rl[1], refresh_level::NONE);
+    assert_eq!(root.rl[0], refresh_level::NONE);
+    assert_eq!(root.rl[3], refresh_level::NONE);
+    assert_eq!(root.me[2][12], 5.0);
+    assert_eq!(root.me[3], me);
+    assert_eq!(root.bbox[2], [15.0, 10.0, 25.0, 20.0]);
+    // 下一帧重置
+    root.refresh();
+    assert!(root.rl.iter().all(|&x| x == refresh_level::NONE));
+  }
+
+  #[test]
+  fn subtree() {
+    let mut root = Root::new();
+    let r = add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 10.0, 10.0, 50.0, 50.0);
+    add(&mut root, 1, 70.0, 70.0, 10.0, 10.0);
+    root.refresh();
+    let me = root.me[2];
+    let list = vec![new_node(1, 0.0, 0.0, 20.0, 20.0), new_node(2, 0.0, 0.0, 10.0, 10.0),
+      new_node(3, 0.0, 0.0, 5.0, 5.0), new_node(2, 10.0, 10.0, 10.0, 10.0)];
+    let nodes: Vec<*mut Node> = list.iter().map(|&x| wasm_ptr::transform_mut(x)).collect();
+    root.insert_subtree(2, list.iter().map(|&x| x as usize).collect());
+    assert_eq!(root.size(), 7);
+    unsafe {
+      assert_eq!((*r).total, 6);
+      assert_eq!((*a).total, 0);
+      assert_eq!(nodes.iter().map(|&x| (*x).total).collect::<Vec<usize>>(), vec![3, 1, 0, 0]);
+      assert_eq!((*nodes[0]).parent, r);
+      assert_eq!((*nodes[1]).parent, nodes[0]);
+      assert_eq!((*nodes[2]).parent, nodes[1]);
+      assert_eq!((*nodes[3]).parent, nodes[0]);
+      assert!(nodes.iter().all(|&x| (*x).root == &mut root as *mut Root));
+    }
+    // 缓存和节点列表对齐，之后的节点复用原本的数据
+    assert_eq!(root.rl.len(), 7);
+    assert_eq!(root.bbox.len(), 7);
+    assert_eq!(root.me[6], me);
+    root.refresh();
+    assert_eq!(root.bbox[5], [10.0, 10.0, 20.0, 20.0]);
+    assert_eq!(root.rl[6], refresh_level::NONE);
+    // 插入到a的子树中
+    let f = new_node(2, 0.0, 0.0, 10.0, 10.0);
+    root.insert_subtree(2, vec![f as usize]);
+    unsafe {
+      assert_eq!((*a).total, 1);
+      assert_eq!((*r).total, 7);
+      assert_eq!((*wasm_ptr::transform_mut(f)).parent, a);
+    }
+    root.refresh();
+    assert_eq!(root.remove_subtree(3), 4);
+    assert_eq!(root.size(), 4);
+    unsafe {
+      assert_eq!((*r).total, 3);
+      assert!(nodes.iter().all(|&x| (*x).root.is_null() && (*x).parent.is_null()));
+    }
+    assert_eq!(root.me.len(), 4);
+    assert_eq!(root.me[3], me);
+    // 移除的区域下次refresh重绘
+    assert_eq!(root.removed.len(), 4);
+    root.refresh();
+    assert_eq!(root.dirty, vec![[0.0, 0.0, 20.0, 20.0]]);
+  }
+
+  #[test]
+  fn resize_recal() {
+    let mut root = Root::new();
+    root.set_font_size(16.0);
+    root.resize(100.0, 100.0);
+    add(&mut root, 0, 0.0, 0.0, 100.0, 100.0);
+    let a = add(&mut root, 1, 0.0, 0.0, 10.0, 10.0);
+    let b = add(&mut root, 1, 0.0, 0.0, 10.0, 10.0);
+    let a = unsafe { &mut *a };
+    let b = unsafe { &mut *b };
+    a.equal_set_style(TRANSLATE_X, 10.0, style_unit::VW);
+    a.equal_set_style(TRANSLATE_Y, 1.0, style_unit::REM);
+    a.cal_matrix(a.get_rl());
+    b.set_font_size(10.0, style_unit::VMIN);
+    b.equal_set_style(TRANSLATE_X, 2.0, style_unit::EM);
+    b.cal_matrix(b.get_rl());
+    root.refresh();
+    assert_eq!(root.me[1][12], 10.0);
+    assert_eq!(root.me[1][13], 16.0);
+    assert_eq!(root.me[2][12], 20.0);
+    // 视口单位和依赖视口的font-size随之更新
+    root.resize(200.0, 50.0);
+    assert_ne!(a.get_rl(), refresh_level::NONE);
+    root.refresh();
+    assert_eq!(root.me[1][12], 20.0);
+    assert_eq!(root.me[1][13], 16.0);
+    assert_eq!(b.font_size, 5.0);
+    assert_eq!(root.me[2][12], 10.0);
+    root.set_font_size(20.0);
+    root.refresh();
+    assert_eq!(root.me[1][13], 20.0);
+    // 尺寸不变无需重新计算
+    root.resize(200.0, 50.0);
+    assert_eq!(a.get_rl(), refresh_level::NONE);
+  }
+}
@@ -0,0 +1,20 @@
+// calc()中的一项，各项单位不同，计算时分别换算后相加，如calc(50% - 10px)为[50%, -10px]
+#[derive(Clone, PartialEq)]
+pub struct CalcItem {
+  pub v: f64,
+  pub u: usize,
+}
+
+impl CalcItem {
+  pub fn new(v: f64, u: usize) -> CalcItem {
+    CalcItem {
+      v,
+      u,
+    }
+  }
+}
+
+// js传入的数值和单位两个数组转为calc
+pub fn to_calc(v: &[f64], u: &[usize]) -> Vec<CalcItem> {
+  v.iter().zip(u.iter()).map(|(&v, &u)| CalcItem::new(v, u)).collect()
+}
@@ -17,4 +17,13 @@ pub mod style_key {
   pub const OPACITY: usize = 15;
   pub const TFO_X: usize = 16;
   pub const TFO_Y: usize = 17;
+  pub const PERSPECTIVE: usize = 18;
+  pub const PERSPECTIVE_ORIGIN_X: usize = 19;
+  pub const PERSPECTIVE_ORIGIN_Y: usize = 20;
+  pub const TFO_Z: usize = 21;
+  pub const LENGTH: usize = 22; // 数值样式的数量，current_style等数组长度
+  // 颜色样式为rgba4个分量，单独存储在current_color中，下标为k - COLOR，
+  // 依次为color、background-color、border-top/right/bottom/left-color，wasm中统一处理无需单独的key
+  pub const COLOR: usize = 22;
+  pub const COLOR_LENGTH: usize = 6;
 }
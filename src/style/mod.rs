@@ -1,5 +1,7 @@
 mod key;
 mod unit;
+mod calc;
 
 pub use key::style_key;
 pub use unit::style_unit;
+pub use calc::{CalcItem, to_calc};
@@ -13,4 +13,5 @@ pub mod style_unit {
   pub const VH: usize = 11;
   pub const VMAX: usize = 12;
   pub const VMIN: usize = 13;
+  pub const CALC: usize = 14; // 多个单位混合，数值存在calc列表中
 }
@@ -0,0 +1,61 @@
+// 各模块测试共用的构造和驱动方法，指针均模拟js侧持有
+use crate::wasm_ptr;
+use crate::node::{Node, Root};
+use crate::animation::{Animation, NORMAL, LINEAR};
+use crate::style::style_unit;
+use crate::style::style_key::{TRANSLATE_X, SCALE_X, SCALE_Y, SCALE_Z};
+
+// js侧指针转为rust引用
+pub fn get<T>(ptr: *mut T) -> &'static mut T {
+  unsafe { &mut *wasm_ptr::transform_mut(ptr) }
+}
+
+// scale默认为0矩阵不可逆，需设为1，返回js侧的node指针
+pub fn new_node(lv: usize, x: f64, y: f64, w: f64, h: f64) -> *mut Node {
+  let mut node = Node::new(false);
+  node.lv = lv;
+  for k in [SCALE_X, SCALE_Y, SCALE_Z] {
+    node.update_style(k, 1.0, style_unit::NUMBER);
+  }
+  node.set_xywh(x, y, w, h);
+  wasm_ptr::from_rust(node)
+}
+
+// 返回rust侧的node指针
+pub fn add_node(root: &mut Root, lv: usize, x: f64, y: f64, w: f64, h: f64) -> *mut Node {
+  let node = new_node(lv, x, y, w, h);
+  root.add_node(node);
+  wasm_ptr::transform_mut(node)
+}
+
+// translateX在duration内从0到100，origin为5px，返回js侧的animation指针
+pub fn add_ani(node: *mut Node, duration: f64, fill: u8, playback_rate: f64, iterations: usize) -> *mut Animation {
+  let mut ani = Animation::from_node(node, NORMAL, duration, 0, 0.0, 0.0, fill,
+    playback_rate, iterations, 0.0, 0.0, LINEAR);
+  ani.add_frame(false, 0.0, LINEAR, 0.0, 0.0, 1.0, 1.0);
+  ani.add_item(false, TRANSLATE_X, 0.0, style_unit::PX, 100.0);
+  ani.add_frame(false, duration, LINEAR, 0.0, 0.0, 1.0, 1.0);
+  ani.add_item(false, TRANSLATE_X, 100.0, style_unit::PX, 0.0);
+  ani.add_origin(TRANSLATE_X, 5.0, style_unit::PX);
+  let ani = wasm_ptr::from_rust(ani);
+  unsafe { &mut *node }.add_ani(ani);
+  ani
+}
+
+pub fn translate_x(node: *mut Node) -> f64 {
+  unsafe { *(*node).computed_style_ptr().add(TRANSLATE_X) }
+}
+
+pub fn events(root: &Root) -> Vec<[f64; 4]> {
+  unsafe { std::slice::from_raw_parts(root.events_ptr(), root.events_size()) }.to_vec()
+}
+
+// 仅事件类型
+pub fn kinds(root: &Root) -> Vec<u8> {
+  events(root).iter().map(|e| e[0] as u8).collect()
+}
+
+pub fn tick(root: &mut Root, diff: f64) -> () {
+  root.before(diff);
+  root.after();
+}